@@ -1,41 +1,21 @@
 #![allow(dead_code)]
 
 use crate::mancala::{MancalaEndgamesTable, MancalaGameState};
-use crate::minimax::{EndgamesTable, GameState, Solver};
+use crate::minimax::{
+    load_transposition_table, EndgamesTable, GameState, SearchLimits, SearchResult, Solver,
+    TableSize,
+};
 use std::rc::Rc;
 use std::time::SystemTime;
 use tracing::info;
 
-fn solve_game(minimax_solver: &mut Solver<MancalaGameState, MancalaEndgamesTable>) {
-    let mut game_state_cache = MancalaGameState::generate_children_memory(minimax_solver.max_depth);
-    let mut guess = 0;
-    let mut depth = 0;
-    while depth <= minimax_solver.max_depth {
-        let now = SystemTime::now();
-        guess = minimax_solver.mtdf_with_memory(guess, depth, &mut game_state_cache);
-        let time = now.elapsed().unwrap();
-        info!(
-            "Value: {}, Depth: {}, Time: {}ms",
-            guess,
-            depth,
-            time.as_millis()
-        );
-
-        depth += 2;
-    }
-
-    // final
-    let now = SystemTime::now();
-    let mut game_state_cache_10000 = MancalaGameState::generate_children_memory(10000);
-    guess = minimax_solver.mtdf_with_memory(guess, 10000, &mut game_state_cache_10000);
-
-    let time = now.elapsed().unwrap();
-    info!(
-        "Value: {}, Depth: {}, Time: {}ms",
-        guess,
-        10000,
-        time.as_millis()
-    );
+fn solve_game(
+    minimax_solver: &mut Solver<MancalaGameState, MancalaEndgamesTable>,
+) -> SearchResult<MancalaGameState> {
+    let mut game_state_cache = MancalaGameState::generate_children_memory(10000);
+    minimax_solver
+        .iterative_deepening(SearchLimits::none(), &mut game_state_cache)
+        .expect("Fatal Error")
 }
 
 // TODO optimise this and allow choice of algorithm
@@ -50,86 +30,44 @@ pub fn play_game() {
     endgames_table.calculate_endgames();
     let rc_endgames_table = Rc::new(endgames_table);
 
-    let mut minimax_solver = Solver::new(
+    // carry on from a table saved by an earlier run, see transposition_table_to_file
+    let mut minimax_solver = Solver::with_transposition_table(
         MancalaGameState::default(),
         max_depth,
-        max_table_depth,
-        transposition_table_capacity,
+        load_transposition_table(
+            "transposition_table.bin",
+            max_table_depth,
+            TableSize::Entries(transposition_table_capacity),
+        ),
         &rc_endgames_table,
     );
 
     let now = SystemTime::now();
-    solve_game(&mut minimax_solver);
+    let result = solve_game(&mut minimax_solver);
     let time = now.elapsed().unwrap();
     info!("Total time to solve: {}ms", time.as_millis());
 
-    let mut children_memory = MancalaGameState::generate_children_memory(max_depth);
-    let mut game_state = MancalaGameState::default();
+    // how much each alternative to the best first move gives away
+    let mut game_state_cache = MancalaGameState::generate_children_memory(10000);
+    for analysis in minimax_solver
+        .analyse_moves(None, &mut game_state_cache)
+        .expect("Fatal Error")
+    {
+        info!(
+            "    Move: {}, Eval: {}, Loss: {}",
+            analysis.player_move, analysis.value, analysis.loss
+        );
+    }
 
-    for _move_number in 0..max_table_depth {
+    // play out the line where both players make the best move, every position on it has the root's value
+    info!("Principal variation, Eval: {}", result.value);
+    let mut game_state = MancalaGameState::default();
+    for (best_move, next_game_state) in result.moves.into_iter().zip(result.principal_variation) {
         info!("Game state: {:?}", game_state);
-        if game_state.is_game_over() {
-            return;
-        }
-
-        // get valid moves (for easier tracking)
-        let mut valid_moves = Vec::new();
-        if game_state.turn {
-            for i in 0..6 {
-                if game_state.pits[i] != 0 {
-                    valid_moves.push(i);
-                }
-            }
-        } else {
-            for i in 7..13 {
-                if game_state.pits[i] != 0 {
-                    valid_moves.push(i);
-                }
-            }
-        }
-        info!("Valid moves: {:?}", valid_moves);
-
-        let mut best_guess;
-        if game_state.turn {
-            best_guess = i32::MIN;
-        } else {
-            best_guess = i32::MAX;
-        }
-        let mut next_game_state = MancalaGameState::default();
-
-        // TODO fix with smart reordering
-        let children: Vec<MancalaGameState> = game_state
-            .get_children(&mut children_memory[0])
-            .to_owned()
-            .into_iter()
-            .rev()
-            .collect();
-        for (child, child_move) in children.iter().zip(valid_moves) {
-            if let Some(lookup) = minimax_solver.transposition_table.lookup(child) {
-                if lookup.lower_bound.bound == lookup.upper_bound.bound {
-                    info!(
-                        "    Move: {}, Eval: {}",
-                        child_move, lookup.lower_bound.bound
-                    );
-                    if game_state.turn && lookup.lower_bound.bound > best_guess {
-                        next_game_state = child.clone();
-                        best_guess = lookup.lower_bound.bound;
-                    } else if !game_state.turn && lookup.lower_bound.bound < best_guess {
-                        next_game_state = child.clone();
-                        best_guess = lookup.lower_bound.bound;
-                    }
-                } else {
-                    info!(
-                        "    Move: {}, Eval: {}:{}",
-                        child_move, lookup.lower_bound.bound, lookup.upper_bound.bound
-                    );
-                }
-            } else {
-                info!("Oh nos")
-            }
-        }
+        info!("Valid moves: {:?}", game_state.legal_moves());
+        info!("    Move: {}", best_move);
 
-        info!("best_guess");
-        game_state = next_game_state.clone();
+        game_state = next_game_state;
     }
+    info!("Game state: {:?}", game_state);
 }
@@ -6,10 +6,13 @@ mod minimax;
 //mod endgames;
 
 use mancala::{MancalaEndgamesTable, MancalaGameState};
-use minimax::{EndgamesTable, GameState, Solver};
+use minimax::{
+    load_transposition_table, EndgamesTable, GameState, LazySmpSolver, MonteCarloTreeSearch,
+    Outcome, Playout, ProofNumberSearch, ReplacementPolicy, SearchLimits, Solver, TableSize,
+};
 use std::cmp::max;
 use std::rc::Rc;
-use std::time::SystemTime;
+use std::time::{Duration, SystemTime};
 use tracing::info;
 use tracing::level_filters::LevelFilter;
 
@@ -18,6 +21,11 @@ enum SolverAlgorithm {
     Alphabeta,
     MtdfNoMemory,
     MtdfMemory,
+    // mtdf with memory, making and unmaking moves on one game state instead of using the children cache
+    MtdfMakeUnmake,
+    Pvs,
+    // window is the initial distance either side of the previous iteration's value
+    Aspiration(i32),
 }
 
 fn analyse(
@@ -39,6 +47,7 @@ fn analyse(
         &rc_endgames_table,
     );
     let mut game_state_cache = MancalaGameState::generate_children_memory(max_depth);
+    let mut moves_cache = MancalaGameState::generate_moves_memory(max_depth);
     let mut guess = 0;
     for depth in 0..(max_depth + 1) {
         // start timer
@@ -60,6 +69,27 @@ fn analyse(
             SolverAlgorithm::MtdfMemory => {
                 minimax_solver.mtdf_with_memory(guess, depth, &mut game_state_cache)
             }
+            SolverAlgorithm::MtdfMakeUnmake => {
+                minimax_solver.mtdf_make_unmake(guess, depth, &mut moves_cache)
+            }
+            SolverAlgorithm::Pvs => minimax_solver.pvs_with_memory(
+                &MancalaGameState::default(),
+                depth,
+                0,
+                i32::MIN,
+                i32::MAX,
+                &mut game_state_cache,
+            ),
+            SolverAlgorithm::Aspiration(window) => {
+                let (value, re_searches) = minimax_solver.aspiration_with_memory(
+                    guess,
+                    depth,
+                    window,
+                    &mut game_state_cache,
+                );
+                info!("Window: {}, Re-searches: {}", window, re_searches);
+                value
+            }
         };
         let time = now.elapsed().unwrap();
         info!(
@@ -68,6 +98,7 @@ fn analyse(
             depth,
             time.as_millis()
         );
+        info!("{}", minimax_solver.take_stats());
     }
 
     // // final - 1
@@ -95,6 +126,61 @@ fn analyse(
         10000,
         time.as_millis()
     );
+    info!("{}", minimax_solver.take_stats());
+}
+
+// exact solve of the starting position using several threads
+fn analyse_parallel(
+    num_threads: usize,
+    max_table_depth: u32,
+    transposition_table_capacity: usize,
+    endgames_table_max_stones: u32,
+) {
+    let mut endgames_table = MancalaEndgamesTable::new(endgames_table_max_stones);
+    endgames_table.calculate_endgames();
+
+    let minimax_solver = LazySmpSolver::new(
+        MancalaGameState::default(),
+        num_threads,
+        10000,
+        max_table_depth,
+        transposition_table_capacity,
+        &endgames_table,
+    );
+
+    let now = SystemTime::now();
+    let eval = minimax_solver
+        .mtdf_with_memory_no_depth_limit(0)
+        .expect("Fatal Error");
+    let time = now.elapsed().unwrap();
+    info!(
+        "Value: {}, Threads: {}, Time: {}ms",
+        eval,
+        num_threads,
+        time.as_millis()
+    );
+    info!("{}", minimax_solver.take_stats());
+}
+
+// win/draw/loss for player 1 from the starting position
+fn prove_outcome(endgames_table_max_stones: u32, max_nodes: usize) {
+    let mut endgames_table = MancalaEndgamesTable::new(endgames_table_max_stones);
+    endgames_table.calculate_endgames();
+
+    let mut proof_number_search = ProofNumberSearch::new(&endgames_table, max_nodes);
+
+    let now = SystemTime::now();
+    let outcome = proof_number_search.solve(&MancalaGameState::default());
+    let time = now.elapsed().unwrap();
+    info!(
+        "Outcome: {:?}, Tree size: {}, Time: {}ms",
+        outcome,
+        proof_number_search.tree_size(),
+        time.as_millis()
+    );
+    if outcome == Outcome::Unknown {
+        info!("Not proven within {} nodes", max_nodes);
+    }
 }
 
 fn depth_analysis(game_state: &MancalaGameState, depth: u32, max_depth: u32) -> u32 {
@@ -111,7 +197,7 @@ fn depth_analysis(game_state: &MancalaGameState, depth: u32, max_depth: u32) ->
     }
 
     if new_depth == max_depth {
-        info!("Test: {:?}", game_state.pits);
+        info!("Test: {:?}", game_state.pits());
     }
 
     new_depth
@@ -125,8 +211,8 @@ fn all_positions(
     max_depth: u32,
 ) -> u32 {
     if pit_index == 12 {
-        game_state.pits[12] = remaining_stones;
-        let new_depth = depth_analysis(&game_state, 0, max_depth);
+        game_state.set_pit(12, remaining_stones);
+        let new_depth = depth_analysis(game_state, 0, max_depth);
         if new_depth == max_depth {
             info!("{:?}", game_state);
         }
@@ -141,7 +227,7 @@ fn all_positions(
         );
     } else {
         for i in 0..(remaining_stones + 1) {
-            game_state.pits[pit_index] = i;
+            game_state.set_pit(pit_index, i);
             depth = max(
                 depth,
                 all_positions(
@@ -174,8 +260,6 @@ enum MancalaMove {
     F,
 }
 
-use MancalaMove::{A, B, C, D, E, F};
-
 impl MancalaMove {
     pub fn from_string(str: &str) -> Option<Self> {
         match str.to_ascii_uppercase().as_str() {
@@ -228,76 +312,102 @@ fn make_all_moves(
     start_game_state: MancalaGameState,
     moves: &Vec<MancalaMove>,
 ) -> MancalaGameState {
-    let mut game_state = start_game_state.clone();
+    let mut game_state = start_game_state;
     for mancala_move in moves {
         let mut move_index = mancala_move.to_index();
-        let players_store;
-        let opponents_store;
-        if game_state.turn {
-            players_store = MancalaGameState::PLAYER_1_STORE;
-            opponents_store = MancalaGameState::PLAYER_2_STORE;
-        } else {
+        if !game_state.turn() {
             move_index += MancalaGameState::PLAYER_1_STORE + 1;
-            players_store = MancalaGameState::PLAYER_2_STORE;
-            opponents_store = MancalaGameState::PLAYER_1_STORE;
         }
 
-        if game_state.pits[move_index] == 0 {
+        if game_state.pits()[move_index] == 0 {
             println!("Fatal Error. move_index: {}", move_index);
         }
 
-        game_state.make_move(move_index, players_store, opponents_store);
+        game_state = game_state.apply(move_index);
     }
 
     game_state
 }
 
-fn full_solve(
-    start_game_state: MancalaGameState,
-    endgames_table: &Rc<MancalaEndgamesTable>,
-) -> (usize, i32) {
-    let max_depth = 28;
-    let max_table_depth = 20;
-    let transposition_table_capacity = 10000000;
+// exact solve of the opening, saving the transposition table to seed later runs
+fn transposition_table_to_file(
+    max_table_depth: u32,
+    transposition_table_capacity: usize,
+    endgames_table_max_stones: u32,
+) {
+    let filepath = "transposition_table.bin";
+    let mut endgames_table = MancalaEndgamesTable::new(endgames_table_max_stones);
+    endgames_table.calculate_endgames();
+    let rc_endgames_table = Rc::new(endgames_table);
 
-    let mut minimax_solver = Solver::new(
-        start_game_state,
-        max_depth,
-        max_table_depth,
-        transposition_table_capacity,
-        endgames_table,
+    let mut minimax_solver = Solver::with_transposition_table(
+        MancalaGameState::default(),
+        u32::MAX,
+        load_transposition_table(
+            filepath,
+            max_table_depth,
+            TableSize::Entries(transposition_table_capacity),
+        ),
+        &rc_endgames_table,
     );
     let mut game_state_cache = MancalaGameState::generate_children_memory(10000);
 
-    let mut guess = 0;
-    let mut depth = 0;
-    while depth <= max_depth {
-        guess = minimax_solver.mtdf_with_memory(guess, depth, &mut game_state_cache);
-        depth += 2;
-    }
-    let eval = minimax_solver.mtdf_with_memory_no_depth_limit(guess, &mut game_state_cache);
+    let now = SystemTime::now();
+    let result = minimax_solver.solve(0, &mut game_state_cache);
+    info!(
+        "Value: {}, Time: {}ms, Entries: {}",
+        result.value,
+        now.elapsed().unwrap().as_millis(),
+        minimax_solver.transposition_table.len()
+    );
+
+    minimax_solver
+        .transposition_table
+        .write_to_file(filepath)
+        .expect("Couldn't save the transposition table");
+}
 
-    // get move
-    let children = start_game_state.get_children_naive();
-    let valid_moves = start_game_state.get_valid_moves();
+fn full_solve(
+    minimax_solver: &mut Solver<MancalaGameState, MancalaEndgamesTable>,
+    game_state: MancalaGameState,
+    limits: SearchLimits,
+) -> (usize, Option<i32>) {
+    // a game only moves forward, so entries which haven't been needed for a few moves are unlikely to be needed again
+    // a fixed table replaces those by itself, one which grows has them removed once it gets this big
+    let max_table_age = 4;
+    let transposition_table_capacity = 10000000;
 
-    for (child, child_move) in children.iter().zip(valid_moves) {
-        if let Some(lookup) = endgames_table.lookup(child) {
-            if lookup == eval {
-                return (child_move, eval);
-            }
-        } else if let Some(lookup) = minimax_solver.transposition_table.lookup(child) {
-            if lookup.lower_bound.bound == lookup.upper_bound.bound
-                && lookup.lower_bound.bound == eval
-            {
-                return (child_move, eval);
-            }
+    minimax_solver.advance(&game_state);
+    let table = &mut minimax_solver.transposition_table;
+    if table.capacity().is_none() && table.len() > transposition_table_capacity {
+        let removed = table.remove_stale(max_table_age);
+        info!("Removed {} stale transposition table entries", removed);
+    }
+    let mut game_state_cache = MancalaGameState::generate_children_memory(10000);
+
+    if let Some(result) = minimax_solver.iterative_deepening(limits, &mut game_state_cache) {
+        if let Some(best_move) = result.best_move {
+            return (best_move, Some(result.value));
         }
     }
 
-    println!("Move not found");
+    // not even the first iteration finished, so fall back on the best move an earlier search left in the table
+    // (children are in the same order as their moves) or failing that any legal move
+    info!("Ran out of time before choosing a move");
+    let mut moves = Vec::new();
+    game_state.get_children_moves(&mut moves);
+    let table_move = minimax_solver
+        .transposition_table
+        .lookup(&game_state)
+        .and_then(|element| element.best_child)
+        .and_then(|best_child| moves.get(best_child as usize).copied());
+    let best_move = table_move.unwrap_or_else(|| game_state.legal_moves()[0]);
+    (best_move, None)
+}
 
-    (404, eval)
+enum Bot {
+    AlphaBeta,
+    MonteCarlo,
 }
 
 fn testing() {
@@ -308,8 +418,23 @@ fn testing() {
     // endgames_table.write_to_file("test.bin");
     // let endgames_table_rc = Rc::new(endgames_table.clone());
 
-    let endgames_table = MancalaEndgamesTable::read_from_file("endgames.bin");
+    // mapped rather than read so the bot starts straight away, lookups fault in the parts of the file they need
+    let endgames_table =
+        MancalaEndgamesTable::map_file("endgames.bin").expect("Couldn't load endgames.bin");
     let endgames_table_rc = Rc::new(endgames_table);
+    // one solver for the whole game so each move carries on from the last
+    // it starts from what an earlier exact solve found, see transposition_table_to_file
+    let mut minimax_solver = Solver::with_transposition_table(
+        MancalaGameState::default(),
+        28,
+        load_transposition_table(
+            "transposition_table.bin",
+            20,
+            TableSize::Megabytes(512, ReplacementPolicy::TwoTier),
+        ),
+        &endgames_table_rc,
+    );
+    let think_time = Duration::from_secs(30);
     println!("Woah! I'm ready!");
 
     // get user player
@@ -343,6 +468,42 @@ fn testing() {
         }
     }
 
+    // get bot
+    let bot;
+    loop {
+        println!("Which bot would you like to play? [alphabeta, mcts]");
+        let mut buffer = String::new();
+        std::io::stdin().read_line(&mut buffer).unwrap();
+
+        match buffer.trim() {
+            "alphabeta" => {
+                bot = Bot::AlphaBeta;
+                break;
+            }
+            "mcts" => {
+                bot = Bot::MonteCarlo;
+                break;
+            }
+            _ => println!(
+                "Unexpected input. You entered: {}. Expected: [alphabeta, mcts].",
+                buffer.trim()
+            ),
+        }
+    }
+
+    // the tree is kept between moves so the bot carries on from where it left off
+    let seed = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as u64;
+    let mut monte_carlo_tree_search = MonteCarloTreeSearch::new(
+        MancalaGameState::default(),
+        endgames_table_rc.as_ref(),
+        std::f64::consts::SQRT_2,
+        Playout::Random,
+        seed,
+    );
+
     let mut game_state;
     let mut moves: Vec<MancalaMove> = Vec::new();
 
@@ -363,7 +524,7 @@ fn testing() {
         game_state.pretty_print();
 
         // if it's the player's turn
-        if game_state.turn == user_player {
+        if game_state.turn() == user_player {
             loop {
                 // get valid moves
                 let valid_moves = game_state.get_valid_moves();
@@ -396,23 +557,89 @@ fn testing() {
             }
         } else {
             println!("Doing another big think 🤔... What move to make...");
-            let (move_index, eval) = full_solve(game_state, &endgames_table_rc);
-            let mancala_move = MancalaMove::from_index(&move_index).expect("Fatal Error");
-            println!(
-                "Making move: {}. I evaluate it as: {}",
-                mancala_move.to_str(),
-                eval
-            );
+            let mancala_move = match bot {
+                Bot::AlphaBeta => {
+                    let (move_index, eval) = full_solve(
+                        &mut minimax_solver,
+                        game_state,
+                        SearchLimits::time(think_time),
+                    );
+                    let mancala_move = MancalaMove::from_index(&move_index).expect("Fatal Error");
+                    match eval {
+                        Some(eval) => println!(
+                            "Making move: {}. I evaluate it as: {}",
+                            mancala_move.to_str(),
+                            eval
+                        ),
+                        None => println!(
+                            "Making move: {}. I didn't have time to evaluate it",
+                            mancala_move.to_str()
+                        ),
+                    }
+                    mancala_move
+                }
+                Bot::MonteCarlo => {
+                    monte_carlo_tree_search.advance(&game_state);
+                    monte_carlo_tree_search.search(&SearchLimits::time(think_time));
+                    let move_index = monte_carlo_tree_search.best_move().expect("Fatal Error");
+                    let mancala_move = MancalaMove::from_index(&move_index).expect("Fatal Error");
+                    println!(
+                        "Making move: {}. I played {} games to decide",
+                        mancala_move.to_str(),
+                        monte_carlo_tree_search.root_visits()
+                    );
+                    mancala_move
+                }
+            };
             moves.push(mancala_move);
         }
     }
 }
 
-fn endgames_to_file(num_stones: u32) {
+// picks up from endgames.bin if it's there, see MancalaEndgamesTable::calculate_endgames_to_file
+// num_workers is the number of threads, None for one per core
+fn endgames_to_file(num_stones: u32, num_workers: Option<usize>) {
     let filepath = "endgames.bin";
     let mut endgames_table = MancalaEndgamesTable::new(num_stones);
-    endgames_table.calculate_endgames();
-    endgames_table.write_to_file(filepath);
+    endgames_table.num_workers = num_workers;
+    endgames_table
+        .calculate_endgames_to_file(filepath)
+        .expect("Couldn't save the endgames table");
+}
+
+// rewrite an endgames table saved before the file format had a header
+fn upgrade_endgames_file(filepath: &str) {
+    let endgames_table = MancalaEndgamesTable::read_from_unversioned_file(filepath)
+        .expect("Couldn't load the old endgames table");
+    endgames_table
+        .write_to_file(filepath)
+        .expect("Couldn't save the endgames table");
+}
+
+// rewrite an endgames table in the block-compressed format, see MancalaEndgamesTable::write_compressed_file
+fn compress_endgames_file(filepath: &str, compressed_filepath: &str, block_size: u32, level: i32) {
+    let endgames_table =
+        MancalaEndgamesTable::map_file(filepath).expect("Couldn't load the endgames table");
+    let sizes = endgames_table
+        .write_compressed_file(compressed_filepath, block_size, level)
+        .expect("Couldn't save the compressed endgames table");
+    for (num_stones, (raw, compressed)) in sizes.iter().enumerate() {
+        info!(
+            "{} stones: {} bytes -> {} bytes, ratio {:.2}",
+            num_stones,
+            raw,
+            compressed,
+            *raw as f64 / max(*compressed, 1) as f64
+        );
+    }
+    let raw: usize = sizes.iter().map(|(raw, _)| raw).sum();
+    let compressed: usize = sizes.iter().map(|(_, compressed)| compressed).sum();
+    info!(
+        "Total: {} bytes -> {} bytes, ratio {:.2}",
+        raw,
+        compressed,
+        raw as f64 / max(compressed, 1) as f64
+    );
 }
 
 fn main() {
@@ -425,7 +652,10 @@ fn main() {
 
     //MancalaEndgamesTable::test_table_validity(3);
     //MancalaEndgamesTable::test_table_accuracy(5);
-    //endgames_to_file(30);
+    //endgames_to_file(30, None);
+    //upgrade_endgames_file("endgames.bin");
+    //compress_endgames_file("endgames.bin", "endgames.zst.bin", 16384, 19);
+    //transposition_table_to_file(20, 10000000, 22);
     testing();
 
     // let test_state = MancalaGameState {
@@ -457,6 +687,8 @@ fn main() {
 
     // println!("eval: {}", eval);
     //analyse(SolverAlgorithm::MtdfMemory, 28, 20, 10000000, 2);
+    //analyse_parallel(32, 20, 10000000, 22);
+    //prove_outcome(22, 100000000);
     //interactive::play_game();
 
     // MancalaEndgamesTable::test_index_validity(8);
@@ -470,8 +702,8 @@ fn main() {
     // println!("{:?}", eval);
 
     // let mut game_state = MancalaGameState::new();
-    // game_state.pits[0] = 1;
-    // game_state.pits[7] = 1;
+    // game_state.set_pit(0, 1);
+    // game_state.set_pit(7, 1);
     // depth_analysis(&game_state, 0);
 
     // info!("Alphabeta");
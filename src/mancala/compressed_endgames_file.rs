@@ -0,0 +1,209 @@
+use memmap2::Mmap;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+
+// the entries of an endgames table file, compressed in blocks so any entry can be read without the rest
+// entries are split into segments (one per stone count for endgames tables), then each segment into blocks of
+// block_size entries which are compressed separately with zstd, after the header comes the offset of every block
+// the file is memory mapped like MappedEndgamesFile and recently used blocks are kept decompressed
+#[derive(Clone)]
+pub struct CompressedEndgamesFile {
+    inner: Arc<CompressedEndgamesFileInner>,
+}
+
+struct CompressedEndgamesFileInner {
+    mmap: Mmap,
+    // where the compressed blocks start, after the header and the block offsets
+    data_offset: usize,
+    // num_blocks + 1 offsets into the compressed data, block i is block_offsets[i]..block_offsets[i + 1]
+    block_offsets: Vec<u64>,
+    // first entry and first block of each segment, with an extra element at the end for the totals
+    segment_starts: Vec<usize>,
+    segment_first_blocks: Vec<usize>,
+    block_size: usize,
+    // direct mapped on block number, each slot has its own lock so threads rarely wait for each other
+    cache: Vec<Mutex<CachedBlock>>,
+}
+
+struct CachedBlock {
+    block: usize,
+    entries: Vec<u8>,
+}
+
+impl CompressedEndgamesFile {
+    // memory for blocks kept decompressed, probes jump all over the table so a miss costs a whole block
+    const CACHE_BYTES: usize = 64 * 1024 * 1024;
+
+    // write entries in the compressed layout, returning the compressed size of each segment
+    // segment_starts are the first entry of each segment followed by entries.len()
+    // zstd levels go from 1 (fast) to 22 (small), decompression is fast whichever is used
+    pub fn write(
+        writer: &mut impl Write,
+        entries: &[u8],
+        segment_starts: &[usize],
+        block_size: usize,
+        level: i32,
+    ) -> io::Result<Vec<usize>> {
+        let mut compressor = zstd::bulk::Compressor::new(level)?;
+        // so a corrupted block is noticed when it's decompressed
+        compressor.set_parameter(zstd::zstd_safe::CParameter::ChecksumFlag(true))?;
+
+        let mut blocks = Vec::new();
+        let mut segment_sizes = Vec::with_capacity(segment_starts.len() - 1);
+        for segment in segment_starts.windows(2) {
+            let mut segment_size = 0;
+            for block in entries[segment[0]..segment[1]].chunks(block_size) {
+                let compressed = compressor.compress(block)?;
+                segment_size += compressed.len();
+                blocks.push(compressed);
+            }
+            segment_sizes.push(segment_size);
+        }
+
+        let mut offset: u64 = 0;
+        writer.write_all(&offset.to_le_bytes())?;
+        for block in blocks.iter() {
+            offset += block.len() as u64;
+            writer.write_all(&offset.to_le_bytes())?;
+        }
+        for block in blocks.iter() {
+            writer.write_all(block)?;
+        }
+
+        Ok(segment_sizes)
+    }
+
+    // map a file written by write after a header of header_len bytes, segment_starts and block_size have to be
+    // the ones it was written with (they come from the header)
+    pub fn open(
+        filepath: &str,
+        header_len: usize,
+        segment_starts: Vec<usize>,
+        block_size: usize,
+    ) -> io::Result<Self> {
+        let invalid_data = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let file = File::open(filepath)?;
+        // see MappedEndgamesFile::open
+        let mmap = unsafe { Mmap::map(&file)? };
+
+        let mut segment_first_blocks = Vec::with_capacity(segment_starts.len());
+        let mut num_blocks = 0;
+        for segment in segment_starts.windows(2) {
+            segment_first_blocks.push(num_blocks);
+            num_blocks += (segment[1] - segment[0]).div_ceil(block_size);
+        }
+        segment_first_blocks.push(num_blocks);
+
+        let data_offset = header_len + (num_blocks + 1) * 8;
+        if mmap.len() < data_offset {
+            return Err(invalid_data(format!(
+                "{} bytes is too short for the header and {} block offsets",
+                mmap.len(),
+                num_blocks + 1
+            )));
+        }
+        let block_offsets: Vec<u64> = mmap[header_len..data_offset]
+            .chunks(8)
+            .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        if block_offsets
+            .windows(2)
+            .any(|offsets| offsets[0] > offsets[1])
+            || block_offsets[num_blocks] != (mmap.len() - data_offset) as u64
+        {
+            return Err(invalid_data(
+                "block offsets don't match the compressed data, the file is truncated or corrupted"
+                    .to_string(),
+            ));
+        }
+
+        Ok(CompressedEndgamesFile {
+            inner: Arc::new(CompressedEndgamesFileInner {
+                mmap,
+                data_offset,
+                block_offsets,
+                segment_starts,
+                segment_first_blocks,
+                block_size,
+                cache: (0..(Self::CACHE_BYTES / block_size).clamp(1, num_blocks.max(1)))
+                    .map(|_| {
+                        Mutex::new(CachedBlock {
+                            block: usize::MAX,
+                            entries: Vec::new(),
+                        })
+                    })
+                    .collect(),
+            }),
+        })
+    }
+
+    pub fn evaluation(&self, index: usize) -> i8 {
+        let inner = &self.inner;
+        let segment = inner
+            .segment_starts
+            .partition_point(|&start| start <= index)
+            - 1;
+        let index_in_segment = index - inner.segment_starts[segment];
+        let block = inner.segment_first_blocks[segment] + index_in_segment / inner.block_size;
+
+        let mut cached = inner.cache[block % inner.cache.len()].lock().unwrap();
+        if cached.block != block {
+            self.decompress_block(block, &mut cached.entries);
+            cached.block = block;
+        }
+        cached.entries[index_in_segment % inner.block_size] as i8
+    }
+
+    // every entry, decompressed
+    pub fn entries(&self) -> Vec<u8> {
+        let inner = &self.inner;
+        let mut entries = Vec::with_capacity(*inner.segment_starts.last().unwrap());
+        let mut block_entries = Vec::new();
+        for block in 0..inner.block_offsets.len() - 1 {
+            self.decompress_block(block, &mut block_entries);
+            entries.extend_from_slice(&block_entries);
+        }
+        entries
+    }
+
+    // size of the file, header included
+    pub fn len(&self) -> usize {
+        self.inner.mmap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.inner.mmap.is_empty()
+    }
+
+    fn decompress_block(&self, block: usize, entries: &mut Vec<u8>) {
+        let inner = &self.inner;
+        let start = inner.data_offset + inner.block_offsets[block] as usize;
+        let end = inner.data_offset + inner.block_offsets[block + 1] as usize;
+        entries.resize(inner.block_size, 0);
+        // nothing can be looked up in a corrupted table, and it's only found out part way through a search
+        let len = zstd::bulk::decompress_to_buffer(&inner.mmap[start..end], entries)
+            .unwrap_or_else(|error| panic!("corrupted endgames table block {}: {}", block, error));
+        entries.truncate(len);
+    }
+}
+
+impl fmt::Debug for CompressedEndgamesFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CompressedEndgamesFile")
+            .field("bytes", &self.inner.mmap.len())
+            .field("blocks", &(self.inner.block_offsets.len() - 1))
+            .field("block_size", &self.inner.block_size)
+            .finish()
+    }
+}
+
+impl PartialEq for CompressedEndgamesFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.mmap[..] == other.inner.mmap[..]
+    }
+}
+
+impl Eq for CompressedEndgamesFile {}
@@ -2,13 +2,19 @@
 
 use num_integer::binomial;
 use serde::{Deserialize, Serialize};
-use std::fs;
-use std::io::Write;
+use std::borrow::Cow;
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::mem::size_of;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::info;
 
 use crate::{
-    mancala::MancalaGameState,
-    minimax::{EndgamesTable, Solver},
+    mancala::{CompressedEndgamesFile, MancalaGameState, MappedEndgamesFile},
+    minimax::{write_file_replacing, EndgamesTable, GameState, Solver},
 };
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -32,39 +38,375 @@ pub struct MancalaEndgamesTable {
     current_stones: u32,
     // when calculating endgames, not all 48 stones are in play
     stones_in_play: u32,
+    // set by map_file and open_compressed_file, then lookups read the file rather than table, which stays empty
+    #[serde(skip)]
+    file: Option<EndgamesFile>,
+    // threads calculating endgames, None for one per core
+    #[serde(skip)]
+    pub num_workers: Option<usize>,
+    // game states each task works through, small enough to share out evenly and big enough that spawning is cheap
+    #[serde(skip, default = "MancalaEndgamesTable::default_batch_size")]
+    pub batch_size: usize,
+}
+
+// the ways a table can be read straight from a file
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum EndgamesFile {
+    Mapped(MappedEndgamesFile),
+    Compressed(CompressedEndgamesFile),
+}
+
+impl EndgamesFile {
+    fn evaluation(&self, index: usize) -> i8 {
+        match self {
+            EndgamesFile::Mapped(mapped) => mapped.evaluation(index),
+            EndgamesFile::Compressed(compressed) => compressed.evaluation(index),
+        }
+    }
 }
 
 fn factorial(n: u32) -> u128 {
-    return (1..((n as u128) + 1)).product();
+    (1..((n as u128) + 1)).product()
+}
+
+// file format, all numbers little endian:
+//   magic, version, endianness (0 for little), entry width in bytes, pits per side, stones per pit,
+//   max stones, number of entries, checksum of the entries (64-bit FNV-1a), then the entries
+// the rules are in there as a table for a different board is the right size for some max stones but means nothing
+// bump the version whenever any of that changes so old files are refused rather than misread
+// version 1 was a native endian max stones followed by the entries, see read_from_unversioned_file
+const FILE_MAGIC: &[u8; 8] = b"MNCLENDG";
+const FILE_VERSION: u32 = 2;
+const FILE_LITTLE_ENDIAN: u8 = 0;
+const FILE_HEADER_SIZE: usize = 8 + 4 + 1 + 1 + 4 + 4 + 4 + 8 + 8;
+
+// compressed file format: the same header with its own magic and version followed by the entries per block,
+// then the block offsets and blocks described in CompressedEndgamesFile with a segment for each stone count
+// the checksum is of the uncompressed entries, so it matches the raw file the table was converted from
+const COMPRESSED_FILE_MAGIC: &[u8; 8] = b"MNCLENDZ";
+const COMPRESSED_FILE_VERSION: u32 = 1;
+const COMPRESSED_FILE_HEADER_SIZE: usize = FILE_HEADER_SIZE + 4;
+
+// where the checksum is in the header, write_stones_to_file fills it in once every entry has been written
+const FILE_CHECKSUM_OFFSET: u64 = FILE_HEADER_SIZE as u64 - 8;
+// entries converted to bytes at a time when writing an in-memory table, so it's never copied as a whole
+const WRITE_CHUNK_SIZE: usize = 1 << 16;
+
+const CHECKSUM_START: u64 = 0xcbf29ce484222325;
+
+fn checksum(bytes: &[u8]) -> u64 {
+    extend_checksum(CHECKSUM_START, bytes)
+}
+
+// carry on a checksum over more bytes, so it can be worked out a chunk at a time
+fn extend_checksum(mut hash: u64, bytes: &[u8]) -> u64 {
+    for &byte in bytes {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
 }
 
 impl MancalaEndgamesTable {
-    pub fn write_to_file(&self, filepath: &str) {
-        let mut file = fs::OpenOptions::new()
-            .create(true)
-            .write(true)
-            // either use the ? operator or unwrap since it returns a Result
-            .open(filepath)
-            .unwrap();
+    pub fn write_to_file(&self, filepath: &str) -> io::Result<()> {
+        self.write_stones_to_file(filepath, self.max_stones)
+    }
 
-        let num_stones_data = self.max_stones.to_ne_bytes();
-        file.write_all(&num_stones_data).unwrap();
-        let formatted_table: Vec<u8> = self
-            .table
-            .iter()
-            .map(|eval| eval.evaluation as u8)
-            .collect();
-        file.write_all(&formatted_table).unwrap();
+    // save the entries for up to num_stones as a table with that many stones
+    // this is the checkpoint after every layer of calculate_endgames_to_file, so the entries are streamed out and
+    // the checksum worked out as they go, then written into the header at the end
+    fn write_stones_to_file(&self, filepath: &str, num_stones: u32) -> io::Result<()> {
+        let num_entries = Self::total_num_game_states_full_board(num_stones) as usize;
+        write_file_replacing(filepath, |writer| {
+            Self::write_header(writer, FILE_MAGIC, FILE_VERSION, num_stones, num_entries, 0)?;
+            let mut hash = CHECKSUM_START;
+            self.for_each_entries_chunk(num_entries, |chunk| {
+                hash = extend_checksum(hash, chunk);
+                writer.write_all(chunk)
+            })?;
+            writer.seek(SeekFrom::Start(FILE_CHECKSUM_OFFSET))?;
+            writer.write_all(&hash.to_le_bytes())
+        })
     }
 
-    pub fn read_from_file(filepath: &str) -> Self {
-        let mut data: Vec<u8> = fs::read(filepath).unwrap();
+    // the first num_entries entries as bytes, a chunk at a time
+    fn for_each_entries_chunk(
+        &self,
+        num_entries: usize,
+        mut f: impl FnMut(&[u8]) -> io::Result<()>,
+    ) -> io::Result<()> {
+        if self.file.is_some() {
+            return self.entries()[..num_entries]
+                .chunks(WRITE_CHUNK_SIZE)
+                .try_for_each(f);
+        }
+
+        let mut chunk = Vec::with_capacity(WRITE_CHUNK_SIZE);
+        for entries in self.table[..num_entries].chunks(WRITE_CHUNK_SIZE) {
+            chunk.clear();
+            chunk.extend(entries.iter().map(|entry| entry.evaluation as u8));
+            f(&chunk)?;
+        }
+        Ok(())
+    }
+
+    // load a table saved by write_to_file
+    // files for other rules, from another version of the format, truncated or corrupted are refused with InvalidData
+    pub fn read_from_file(filepath: &str) -> io::Result<Self> {
+        let mut data: Vec<u8> = fs::read(filepath)?;
         println!("Read {} MB from disk", data.len() as f32 / 1e6);
-        let table_data = data.split_off(4);
-        let max_stones_data = data;
-        let max_stones = u32::from_ne_bytes(max_stones_data.try_into().unwrap());
+        let header_len = data.len().min(FILE_HEADER_SIZE);
+        let (max_stones, expected_checksum) =
+            Self::read_header(&data[..header_len], FILE_MAGIC, FILE_VERSION)?;
+        let entries = data.split_off(FILE_HEADER_SIZE);
+        Self::check_num_entries(max_stones, entries.len())?;
+        if checksum(&entries) != expected_checksum {
+            return Err(invalid_data(
+                "endgames table checksum doesn't match, the file is corrupted".to_string(),
+            ));
+        }
+
+        Ok(Self::from_entries(max_stones, entries))
+    }
+
+    // use a table saved by write_to_file without reading it into memory, see MappedEndgamesFile
+    // the header is checked like read_from_file, but not the checksum as that would mean reading every entry
+    pub fn map_file(filepath: &str) -> io::Result<Self> {
+        let mapped = MappedEndgamesFile::open(filepath, FILE_HEADER_SIZE)?;
+        let (max_stones, _) = Self::read_header(mapped.header(), FILE_MAGIC, FILE_VERSION)?;
+        Self::check_num_entries(max_stones, mapped.entries().len())?;
+
+        println!("Mapped endgames table with {} stones", max_stones);
+        Ok(Self::from_file(max_stones, EndgamesFile::Mapped(mapped)))
+    }
+
+    // save the table in blocks of block_size entries compressed with zstd at the given level (1 to 22)
+    // returns the uncompressed and compressed size in bytes of the entries for each stone count
+    // see CompressedEndgamesFile, writing a table opened with map_file converts the raw format
+    pub fn write_compressed_file(
+        &self,
+        filepath: &str,
+        block_size: u32,
+        level: i32,
+    ) -> io::Result<Vec<(usize, usize)>> {
+        let entries = self.entries();
+        let segment_starts = Self::segment_starts(self.max_stones);
+        let compressed_sizes = write_file_replacing(filepath, |writer| {
+            Self::write_header(
+                writer,
+                COMPRESSED_FILE_MAGIC,
+                COMPRESSED_FILE_VERSION,
+                self.max_stones,
+                entries.len(),
+                checksum(&entries),
+            )?;
+            writer.write_all(&block_size.to_le_bytes())?;
+            CompressedEndgamesFile::write(
+                writer,
+                &entries,
+                &segment_starts,
+                block_size as usize,
+                level,
+            )
+        })?;
+
+        Ok(segment_starts
+            .windows(2)
+            .map(|segment| segment[1] - segment[0])
+            .zip(compressed_sizes)
+            .collect())
+    }
+
+    // use a table saved by write_compressed_file, mapped like map_file and decompressing blocks as lookups need them
+    // the header and block offsets are checked, each block is checked when it's decompressed
+    pub fn open_compressed_file(filepath: &str) -> io::Result<Self> {
+        let mut header = Vec::with_capacity(COMPRESSED_FILE_HEADER_SIZE);
+        File::open(filepath)?
+            .take(COMPRESSED_FILE_HEADER_SIZE as u64)
+            .read_to_end(&mut header)?;
+        let (max_stones, _) =
+            Self::read_header(&header, COMPRESSED_FILE_MAGIC, COMPRESSED_FILE_VERSION)?;
+        if header.len() < COMPRESSED_FILE_HEADER_SIZE {
+            return Err(invalid_data(format!(
+                "{} bytes is too short for a compressed endgames table header",
+                header.len()
+            )));
+        }
+        let block_size = u32::from_le_bytes(header[FILE_HEADER_SIZE..].try_into().unwrap());
+        if block_size == 0 {
+            return Err(invalid_data("block size can't be 0".to_string()));
+        }
+
+        let compressed = CompressedEndgamesFile::open(
+            filepath,
+            COMPRESSED_FILE_HEADER_SIZE,
+            Self::segment_starts(max_stones),
+            block_size as usize,
+        )?;
+        println!(
+            "Opened compressed endgames table with {} stones, {} MB",
+            max_stones,
+            compressed.len() as f32 / 1e6
+        );
+        Ok(Self::from_file(
+            max_stones,
+            EndgamesFile::Compressed(compressed),
+        ))
+    }
+
+    // every entry as a byte, wherever the table is
+    fn entries(&self) -> Cow<'_, [u8]> {
+        match &self.file {
+            Some(EndgamesFile::Mapped(mapped)) => Cow::Borrowed(mapped.entries()),
+            Some(EndgamesFile::Compressed(compressed)) => Cow::Owned(compressed.entries()),
+            None => Cow::Owned(
+                self.table
+                    .iter()
+                    .map(|eval| eval.evaluation as u8)
+                    .collect(),
+            ),
+        }
+    }
+
+    // where the entries for each number of stones start, followed by the total number of entries
+    fn segment_starts(max_stones: u32) -> Vec<usize> {
+        let mut segment_starts = vec![0];
+        for num_stones in 0..(max_stones + 1) {
+            segment_starts.push(Self::total_num_game_states_full_board(num_stones) as usize);
+        }
+        segment_starts
+    }
+
+    fn write_header(
+        writer: &mut impl Write,
+        magic: &[u8; 8],
+        version: u32,
+        max_stones: u32,
+        num_entries: usize,
+        checksum: u64,
+    ) -> io::Result<()> {
+        writer.write_all(magic)?;
+        writer.write_all(&version.to_le_bytes())?;
+        writer.write_all(&[FILE_LITTLE_ENDIAN, size_of::<TableEntry>() as u8])?;
+        writer.write_all(&(MancalaGameState::PITS_PER_SIDE as u32).to_le_bytes())?;
+        writer.write_all(&MancalaGameState::STONES_PER_PIT.to_le_bytes())?;
+        writer.write_all(&max_stones.to_le_bytes())?;
+        writer.write_all(&(num_entries as u64).to_le_bytes())?;
+        writer.write_all(&checksum.to_le_bytes())
+    }
+
+    // check a header written by write_header, returning the max stones and the checksum of the entries
+    fn read_header(header: &[u8], magic: &[u8; 8], version: u32) -> io::Result<(u32, u64)> {
+        if header.len() < FILE_HEADER_SIZE {
+            return Err(invalid_data(format!(
+                "{} bytes is too short for an endgames table header",
+                header.len()
+            )));
+        }
+
+        let u32_at =
+            |offset: usize| u32::from_le_bytes(header[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_le_bytes(header[offset..offset + 8].try_into().unwrap());
+        if &header[0..8] != magic {
+            return Err(invalid_data(format!(
+                "not an endgames table file of this kind, expected it to start with {}",
+                String::from_utf8_lossy(magic)
+            )));
+        }
+        let file_version = u32_at(8);
+        if file_version != version {
+            return Err(invalid_data(format!(
+                "endgames table file is version {}, expected {}",
+                file_version, version
+            )));
+        }
+        if header[12] != FILE_LITTLE_ENDIAN {
+            return Err(invalid_data(format!("unknown endianness {}", header[12])));
+        }
+        if header[13] as usize != size_of::<TableEntry>() {
+            return Err(invalid_data(format!(
+                "entries are {} bytes wide, expected {}",
+                header[13],
+                size_of::<TableEntry>()
+            )));
+        }
+        let pits_per_side = u32_at(14);
+        let stones_per_pit = u32_at(18);
+        if pits_per_side as usize != MancalaGameState::PITS_PER_SIDE
+            || stones_per_pit != MancalaGameState::STONES_PER_PIT
+        {
+            return Err(invalid_data(format!(
+                "endgames table is for {} pits per side and {} stones per pit, expected {} and {}",
+                pits_per_side,
+                stones_per_pit,
+                MancalaGameState::PITS_PER_SIDE,
+                MancalaGameState::STONES_PER_PIT
+            )));
+        }
+        let max_stones = u32_at(22);
+        if max_stones > MancalaGameState::TOTAL_STONES {
+            return Err(invalid_data(format!(
+                "endgames table has {} stones, more than the game's {}",
+                max_stones,
+                MancalaGameState::TOTAL_STONES
+            )));
+        }
+        let num_entries = u64_at(26);
+        let expected_entries = Self::total_num_game_states_full_board(max_stones);
+        if num_entries as u128 != expected_entries {
+            return Err(invalid_data(format!(
+                "endgames table with {} stones should have {} entries, the header says {}",
+                max_stones, expected_entries, num_entries
+            )));
+        }
+
+        Ok((max_stones, u64_at(34)))
+    }
+
+    fn check_num_entries(max_stones: u32, num_entries: usize) -> io::Result<()> {
+        let expected_entries = Self::total_num_game_states_full_board(max_stones);
+        if num_entries as u128 != expected_entries {
+            return Err(invalid_data(format!(
+                "expected {} entries, the file has {}",
+                expected_entries, num_entries
+            )));
+        }
+        Ok(())
+    }
+
+    // load a file written before the format had a header, so it can be written again with write_to_file
+    // only the length can be checked, and it has to be read on a machine with the same endianness it was written on
+    pub fn read_from_unversioned_file(filepath: &str) -> io::Result<Self> {
+        let mut data: Vec<u8> = fs::read(filepath)?;
+        if data.len() < 4 {
+            return Err(invalid_data(format!(
+                "{} bytes is too short for an endgames table",
+                data.len()
+            )));
+        }
+        let entries = data.split_off(4);
+        let max_stones = u32::from_ne_bytes(data.try_into().unwrap());
+        if max_stones > MancalaGameState::TOTAL_STONES
+            || entries.len() as u128 != Self::total_num_game_states_full_board(max_stones)
+        {
+            return Err(invalid_data(format!(
+                "{} entries doesn't match an endgames table with {} stones",
+                entries.len(),
+                max_stones
+            )));
+        }
+
+        Ok(Self::from_entries(max_stones, entries))
+    }
+
+    fn from_entries(max_stones: u32, entries: Vec<u8>) -> Self {
         let mut endgames_table = Self::new(max_stones);
-        endgames_table.table = table_data
+        endgames_table.table = entries
             .into_iter()
             .map(|byte| TableEntry {
                 evaluation: byte as i8,
@@ -75,93 +417,235 @@ impl MancalaEndgamesTable {
         println!("Endgames table with {} stones", max_stones);
         endgames_table
     }
+
+    fn from_file(max_stones: u32, file: EndgamesFile) -> Self {
+        let mut endgames_table = Self::new(max_stones);
+        endgames_table.file = Some(file);
+        endgames_table.current_stones = max_stones;
+        endgames_table.stones_in_play = MancalaGameState::TOTAL_STONES;
+        endgames_table
+    }
 }
 
-impl EndgamesTable<MancalaGameState> for MancalaEndgamesTable {
-    fn calculate_endgames(&mut self) {
-        let batch_size = 1000;
-        let mut offset = 0;
+impl MancalaEndgamesTable {
+    // work out every game state with num_stones in play and add them to the table
+    // the layers for fewer stones have to be in the table already, as the searches look them up
+    // returns how long it took
+    fn calculate_layer(&mut self, num_stones: u32, rt: &tokio::runtime::Runtime) -> Duration {
+        let start = Instant::now();
+        let batch_size = self.batch_size;
+        let offset = self.table.len();
 
-        let rt = tokio::runtime::Runtime::new().unwrap();
+        // keep track of this so we can do lookups
+        self.stones_in_play = num_stones;
 
-        for num_stones in 0..(self.max_stones + 1) {
-            // keep track of this so we can do lookups
-            self.stones_in_play = num_stones;
+        let num_games = Self::num_game_states_full_board(num_stones) as usize;
 
-            let num_games = Self::num_game_states_full_board(num_stones) as usize;
+        // create memory
+        let table = Self::get_table_memory(num_stones);
 
-            // create memory
-            let table = Self::get_table_memory(num_stones);
+        // lock table and get slice
+        let mut data = table.lock().unwrap();
+        let mut subtable = &mut data[..];
 
-            // lock table and get slice
-            let mut data = table.lock().unwrap();
-            let mut subtable = &mut data[..];
+        // counted as batches finish, for the progress reports
+        let games_done = AtomicUsize::new(0);
 
-            // creates a scope which won't exit until all spawned futures have exited
-            // this allows the lifetime of table to not be static
-            tokio_scoped::scoped(rt.handle()).scope(|scope| {
-                // must reference self here to avoid copying self into each future
-                let endgames_table = &self;
-                let mut batch: &mut [TableEntry];
+        // creates a scope which won't exit until all spawned futures have exited
+        // this allows the lifetime of table to not be static
+        tokio_scoped::scoped(rt.handle()).scope(|scope| {
+            // must reference self here to avoid copying self into each future
+            let endgames_table = &self;
+            let mut batch: &mut [TableEntry];
+            let games_done = &games_done;
 
-                // integer division trick to get the number of batches, including the possibly smaller final batch
-                let num_batches = (num_games + batch_size - 1) / batch_size;
+            // integer division trick to get the number of batches, including the possibly smaller final batch
+            let num_batches = num_games.div_ceil(batch_size);
 
-                for batch_number in 0..num_batches {
-                    // handle final batch being possibly smaller
-                    let current_batch_size;
-                    if batch_number == num_batches - 1 && num_games % batch_size != 0 {
-                        current_batch_size = num_games % batch_size;
+            for batch_number in 0..num_batches {
+                // handle final batch being possibly smaller
+                let current_batch_size =
+                    if batch_number == num_batches - 1 && !num_games.is_multiple_of(batch_size) {
+                        num_games % batch_size
                     } else {
-                        current_batch_size = batch_size;
+                        batch_size
+                    };
+
+                // split off a batch of games to analyse and memory to store the result
+                (batch, subtable) = subtable.split_at_mut(current_batch_size);
+
+                // Use the scope to spawn the future.
+                scope.spawn(async move {
+                    let mut game_state;
+                    // analyse each game state in the batch and store the result in the table
+                    for (batch_index, entry) in batch.iter_mut().enumerate() {
+                        let game_index = offset + batch_number * batch_size + batch_index;
+                        game_state = endgames_table.get_game_state(game_index, num_stones);
+                        game_state.handle_game_over();
+                        *entry = TableEntry {
+                            evaluation: Solver::alphabeta_no_depth_limit(
+                                &game_state,
+                                i32::MIN,
+                                i32::MAX,
+                                *endgames_table,
+                            ) as i8,
+                        };
+                        // this is used for debugging
+                        //batch[batch_index].evaluation += 1;
+                        // batch[batch_index] = TableEntry {
+                        //     evaluation: game_index as i32,
+                        // };
                     }
+                    games_done.fetch_add(batch.len(), Ordering::Relaxed);
+                });
+            }
 
-                    // split off a batch of games to analyse and memory to store the result
-                    (batch, subtable) = subtable.split_at_mut(current_batch_size);
-
-                    // Use the scope to spawn the future.
-                    scope.spawn(async move {
-                        let mut game_state;
-                        // analyse each game state in the batch and store the result in the table
-                        for batch_index in 0..current_batch_size {
-                            let game_index = offset + batch_number * batch_size + batch_index;
-                            game_state = endgames_table.get_game_state(game_index, num_stones);
-                            game_state.handle_game_over();
-                            batch[batch_index] = TableEntry {
-                                evaluation: Solver::alphabeta_no_depth_limit(
-                                    &game_state,
-                                    i32::MIN,
-                                    i32::MAX,
-                                    *endgames_table,
-                                ) as i8,
-                            };
-                            // this is used for debugging
-                            //batch[batch_index].evaluation += 1;
-                            // batch[batch_index] = TableEntry {
-                            //     evaluation: game_index as i32,
-                            // };
-                        }
-                    });
-                }
-            }); // all spawned futures await here
+            // the futures run on the runtime's threads, so this one is free to report on them
+            Self::report_progress(num_stones, num_games, games_done, start);
+        }); // all spawned futures await here
+
+        self.current_stones = num_stones;
+
+        // clone data (need to unlock and relock)
+        drop(data);
+        let data_to_clone = table.lock().unwrap();
+        let subtable_to_clone = &data_to_clone[..];
+        self.table.extend_from_slice(subtable_to_clone);
+        //println!("{:#?}", self.table);
+
+        let elapsed = start.elapsed();
+        info!(
+            "{:>2} stones: {} game states in {:.1}s, {:.0} per second",
+            num_stones,
+            num_games,
+            elapsed.as_secs_f64(),
+            num_games as f64 / elapsed.as_secs_f64()
+        );
+        elapsed
+    }
 
-            offset += num_games;
-            self.current_stones = num_stones;
+    // log how far through a layer the workers are every so often, until they're done
+    fn report_progress(
+        num_stones: u32,
+        num_games: usize,
+        games_done: &AtomicUsize,
+        start: Instant,
+    ) {
+        const REPORT_INTERVAL: Duration = Duration::from_secs(10);
+        const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+        let mut next_report = start + REPORT_INTERVAL;
+        loop {
+            let done = games_done.load(Ordering::Relaxed);
+            if done >= num_games {
+                return;
+            }
+            if Instant::now() >= next_report {
+                let elapsed = start.elapsed().as_secs_f64();
+                let per_second = done as f64 / elapsed;
+                let eta = if done > 0 {
+                    format!("{:.0}s", (num_games - done) as f64 / per_second)
+                } else {
+                    "unknown".to_string()
+                };
+                info!(
+                    "{:>2} stones: {}/{} game states ({:.1}%), {:.0} per second, ETA {}",
+                    num_stones,
+                    done,
+                    num_games,
+                    100.0 * done as f64 / num_games as f64,
+                    per_second,
+                    eta
+                );
+                next_report += REPORT_INTERVAL;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+    }
+
+    fn runtime(&self) -> tokio::runtime::Runtime {
+        let mut builder = tokio::runtime::Builder::new_multi_thread();
+        if let Some(num_workers) = self.num_workers {
+            builder.worker_threads(num_workers);
+        }
+        let rt = builder.build().unwrap();
+        info!(
+            "Calculating endgames up to {} stones with {} workers",
+            self.max_stones,
+            rt.metrics().num_workers()
+        );
+        rt
+    }
 
-            // clone data (need to unlock and relock)
-            drop(data);
-            let data_to_clone = table.lock().unwrap();
-            let subtable_to_clone = &data_to_clone[..];
-            self.table.extend_from_slice(subtable_to_clone);
-            //println!("{:#?}", self.table);
+    // how long each number of stones took, and in total
+    fn log_layer_times(layer_times: &[(u32, Duration)]) {
+        let total: Duration = layer_times.iter().map(|(_, time)| *time).sum();
+        for (num_stones, time) in layer_times.iter() {
+            info!(
+                "{:>2} stones: {:>10.1}s ({:.1}%)",
+                num_stones,
+                time.as_secs_f64(),
+                100.0 * time.as_secs_f64() / total.as_secs_f64().max(f64::MIN_POSITIVE)
+            );
         }
+        info!("Total: {:.1}s", total.as_secs_f64());
+    }
 
+    // calculate_endgames, but saving the table to filepath after each layer so a crash only loses the one in progress
+    // if there's already a table at filepath then only the layers it doesn't have are calculated, so this resumes
+    // an interrupted run and extends a finished table to more stones (a table with more stones is left alone)
+    pub fn calculate_endgames_to_file(&mut self, filepath: &str) -> io::Result<()> {
+        let first_stones = match Self::read_from_file(filepath) {
+            Ok(saved) => {
+                let saved_stones = saved.max_stones.min(self.max_stones);
+                self.table = saved.table;
+                self.table
+                    .truncate(Self::total_num_game_states_full_board(saved_stones) as usize);
+                self.current_stones = saved_stones;
+                info!("Resuming from {} stones", saved_stones);
+                saved_stones + 1
+            }
+            Err(error) if error.kind() == io::ErrorKind::NotFound => 0,
+            Err(error) => return Err(error),
+        };
+        self.table.reserve_exact(
+            Self::total_num_game_states_full_board(self.max_stones) as usize - self.table.len(),
+        );
+
+        let rt = self.runtime();
+
+        let mut layer_times = Vec::new();
+        for num_stones in first_stones..(self.max_stones + 1) {
+            layer_times.push((num_stones, self.calculate_layer(num_stones, &rt)));
+            self.write_stones_to_file(filepath, num_stones)?;
+        }
+
+        self.finish_calculating();
+        Self::log_layer_times(&layer_times);
+        Ok(())
+    }
+
+    fn finish_calculating(&mut self) {
         // this is an invalid game state but I want to handle it properly
         // handle_game_over() doesn't handle this properly so need to set manually
         self.table[0].evaluation = 0;
         assert!(self.current_stones == self.max_stones);
         self.stones_in_play = MancalaGameState::TOTAL_STONES;
     }
+}
+
+impl EndgamesTable<MancalaGameState> for MancalaEndgamesTable {
+    fn calculate_endgames(&mut self) {
+        let rt = self.runtime();
+
+        let mut layer_times = Vec::new();
+        for num_stones in 0..(self.max_stones + 1) {
+            layer_times.push((num_stones, self.calculate_layer(num_stones, &rt)));
+        }
+
+        self.finish_calculating();
+        Self::log_layer_times(&layer_times);
+    }
 
     fn lookup(&self, game_state: &MancalaGameState) -> Option<i32> {
         self.get_value(game_state)
@@ -175,15 +659,22 @@ impl MancalaEndgamesTable {
         let mut endgames_table = MancalaEndgamesTable {
             cache: Vec::with_capacity(max_stones as usize + 1),
             table: Vec::with_capacity(capacity),
-            max_stones: max_stones,
+            max_stones,
             current_stones: 0,
             stones_in_play: 0,
+            file: None,
+            num_workers: None,
+            batch_size: Self::default_batch_size(),
         };
         endgames_table.set_cache(max_stones as usize);
 
         endgames_table
     }
 
+    fn default_batch_size() -> usize {
+        1000
+    }
+
     fn set_cache(&mut self, max_stones: usize) {
         for num_stones in 0..(max_stones + 1) {
             self.cache.push([0; MancalaGameState::PITS_NO_STORES + 2]);
@@ -211,9 +702,9 @@ impl MancalaEndgamesTable {
         let mut index = self.cache[remaining - 1][MancalaGameState::PITS_NO_STORES + 1];
 
         // if player 1
-        if game_state.turn {
+        if game_state.turn() {
             for pit_index in 0..MancalaGameState::PITS_PER_SIDE {
-                remaining -= game_state.pits[pit_index] as usize;
+                remaining -= game_state.pits()[pit_index] as usize;
                 if remaining == 0 {
                     return index;
                 }
@@ -226,7 +717,7 @@ impl MancalaEndgamesTable {
             // don't index the final pit given we know how many stones are in play
             for pit_index in 0..(MancalaGameState::PITS_PER_SIDE - 1) {
                 remaining -=
-                    game_state.pits[pit_index + MancalaGameState::PLAYER_1_STORE + 1] as usize;
+                    game_state.pits()[pit_index + MancalaGameState::PLAYER_1_STORE + 1] as usize;
                 if remaining == 0 {
                     return index;
                 }
@@ -239,7 +730,7 @@ impl MancalaEndgamesTable {
         else {
             for pit_index in 0..(MancalaGameState::PITS_PER_SIDE) {
                 remaining -=
-                    game_state.pits[pit_index + MancalaGameState::PLAYER_1_STORE + 1] as usize;
+                    game_state.pits()[pit_index + MancalaGameState::PLAYER_1_STORE + 1] as usize;
                 if remaining == 0 {
                     return index;
                 }
@@ -249,7 +740,7 @@ impl MancalaEndgamesTable {
             }
 
             for pit_index in 0..(MancalaGameState::PLAYER_1_STORE - 1) {
-                remaining -= game_state.pits[pit_index] as usize;
+                remaining -= game_state.pits()[pit_index] as usize;
                 if remaining == 0 {
                     return index;
                 }
@@ -264,34 +755,29 @@ impl MancalaEndgamesTable {
 
     pub fn get_value(&self, game_state: &MancalaGameState) -> Option<i32> {
         let remaining_stones = self.stones_in_play
-            - game_state.pits[MancalaGameState::PLAYER_1_STORE]
-            - game_state.pits[MancalaGameState::PLAYER_2_STORE];
+            - game_state.pits()[MancalaGameState::PLAYER_1_STORE]
+            - game_state.pits()[MancalaGameState::PLAYER_2_STORE];
         //println!("{:?}", game_state);
         //println!("remaining_stones: {}, stones_in_play: {}, current_stones: {}", remaining_stones, self.stones_in_play, self.current_stones);
-        let result;
         if self.current_stones < remaining_stones {
-            result = None;
+            None
         } else {
             let index = self.get_index(game_state, remaining_stones);
-            let eval = self.table[index].evaluation;
-            let player_eval;
-            if game_state.turn {
-                player_eval = eval;
-            } else {
-                player_eval = -1 * eval;
-            }
-            result = Some(
-                (player_eval + game_state.pits[MancalaGameState::PLAYER_1_STORE] as i8
-                    - game_state.pits[MancalaGameState::PLAYER_2_STORE] as i8)
+            let eval = match &self.file {
+                Some(file) => file.evaluation(index),
+                None => self.table[index].evaluation,
+            };
+            let player_eval = if game_state.turn() { eval } else { -eval };
+            Some(
+                (player_eval + game_state.pits()[MancalaGameState::PLAYER_1_STORE] as i8
+                    - game_state.pits()[MancalaGameState::PLAYER_2_STORE] as i8)
                     as i32,
-            );
+            )
         }
-
-        result
     }
 
     fn get_pit_index(&self, index: usize) -> usize {
-        return index + index / MancalaGameState::PLAYER_1_STORE;
+        index + index / MancalaGameState::PLAYER_1_STORE
     }
 
     // this function can be optimised in many ways
@@ -316,22 +802,21 @@ impl MancalaEndgamesTable {
             for num_stones in (0..(remaining_stones + 1)).rev() {
                 // generate a new index guess
                 let new_remaining = remaining_stones - num_stones;
-                let new_index_guess;
-                if new_remaining == 0 {
-                    new_index_guess = index_guess;
+                let new_index_guess = if new_remaining == 0 {
+                    index_guess
                 } else {
-                    new_index_guess = index_guess
+                    index_guess
                         + self.cache[new_remaining as usize - 1]
-                            [MancalaGameState::PITS_NO_STORES - pit_index];
-                }
+                            [MancalaGameState::PITS_NO_STORES - pit_index]
+                };
 
                 // if the new index guess is correct, or has over corrected
                 if new_index_guess >= index {
                     // if the guess is correct
                     if new_index_guess == index {
-                        game_state.pits[self.get_pit_index(pit_index)] = num_stones;
+                        game_state.set_pit(self.get_pit_index(pit_index), num_stones);
                         if new_remaining != 0 {
-                            game_state.pits[self.get_pit_index(pit_index + 1)] = new_remaining;
+                            game_state.set_pit(self.get_pit_index(pit_index + 1), new_remaining);
                         }
 
                         return game_state;
@@ -345,7 +830,7 @@ impl MancalaEndgamesTable {
             }
 
             // can get to this point either via over correcting or if 0 is the correct number of stones
-            game_state.pits[self.get_pit_index(pit_index)] = next_num_stones;
+            game_state.set_pit(self.get_pit_index(pit_index), next_num_stones);
             remaining_stones -= next_num_stones;
             if remaining_stones == 0 {
                 return game_state;
@@ -390,15 +875,13 @@ impl MancalaEndgamesTable {
         let reserved = std::mem::size_of::<TableEntry>() * num_games;
         let megabytes_int = reserved / 1000000;
         let megabytes_remain = (reserved % 1000000) / 1000;
-        println!(
+        info!(
             "Reserving: {:>6}.{:<3} MB for {:>2} stones. Number of games: {}",
             megabytes_int, megabytes_remain, num_stones, num_games
         );
 
         Arc::new(Mutex::new(
-            std::iter::repeat(TableEntry { evaluation: 0 })
-                .take(num_games)
-                .collect(),
+            std::iter::repeat_n(TableEntry { evaluation: 0 }, num_games).collect(),
         ))
     }
 
@@ -412,7 +895,7 @@ impl MancalaEndgamesTable {
         total_stones: u32,
     ) {
         if pit_index == 12 {
-            game_state.pits[12] = remaining_stones;
+            game_state.set_pit(12, remaining_stones);
 
             // println!("state: {:?}", game_state);
 
@@ -430,7 +913,7 @@ impl MancalaEndgamesTable {
             self.all_positions(game_state, remaining_stones, pit_index + 1, total_stones);
         } else {
             for i in 0..(remaining_stones + 1) {
-                game_state.pits[pit_index] = i;
+                game_state.set_pit(pit_index, i);
                 self.all_positions(
                     game_state,
                     remaining_stones - i,
@@ -447,9 +930,8 @@ impl MancalaEndgamesTable {
         endgames_table.print_cache();
 
         let total_num_games = Self::total_num_game_states_full_board(max_stones) as usize;
-        endgames_table.table = std::iter::repeat(TableEntry { evaluation: 0 })
-            .take(total_num_games)
-            .collect();
+        endgames_table.table =
+            std::iter::repeat_n(TableEntry { evaluation: 0 }, total_num_games).collect();
 
         for i in 0..(max_stones + 1) {
             let num_games = Self::total_num_game_states_full_board(i);
@@ -540,10 +1022,10 @@ impl MancalaEndgamesTable {
             endgames_table.stones_in_play = num_stones;
             for index in 0..num_games {
                 let mut game_state = endgames_table.get_game_state(offset + index, num_stones);
-                game_state.turn = true;
+                game_state.set_turn(true);
                 let table_eval = endgames_table.lookup(&game_state).expect(":O");
                 game_state.handle_game_over();
-                if !game_state.game_over {
+                if !game_state.is_game_over() {
                     let eval = Solver::alphabeta_no_depth_limit(
                         &game_state,
                         i32::MIN,
@@ -564,3 +1046,189 @@ impl MancalaEndgamesTable {
         //println!("{:#?}", endgames_table);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mancala::{MancalaEndgamesTable, MancalaGameState};
+    use crate::minimax::EndgamesTable;
+
+    fn temporary_filepath(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}.bin", name, std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn calculated(max_stones: u32) -> MancalaEndgamesTable {
+        let mut endgames_table = MancalaEndgamesTable::new(max_stones);
+        endgames_table.calculate_endgames();
+        endgames_table
+    }
+
+    // a run stopped after some layers and resumed from its checkpoint ends up with the same table and file
+    #[test]
+    fn checkpoint_resume_matches_one_run() {
+        let filepath = temporary_filepath("endgames-checkpoint");
+        let _ = std::fs::remove_file(&filepath);
+        MancalaEndgamesTable::new(5)
+            .calculate_endgames_to_file(&filepath)
+            .unwrap();
+        let mut resumed = MancalaEndgamesTable::new(8);
+        resumed.calculate_endgames_to_file(&filepath).unwrap();
+
+        let one_run = calculated(8);
+        assert_eq!(resumed.table, one_run.table);
+
+        let one_run_filepath = temporary_filepath("endgames-one-run");
+        one_run.write_to_file(&one_run_filepath).unwrap();
+        assert_eq!(
+            std::fs::read(&filepath).unwrap(),
+            std::fs::read(&one_run_filepath).unwrap()
+        );
+        assert_eq!(
+            MancalaEndgamesTable::read_from_file(&filepath)
+                .unwrap()
+                .table,
+            one_run.table
+        );
+
+        std::fs::remove_file(&filepath).unwrap();
+        std::fs::remove_file(&one_run_filepath).unwrap();
+    }
+
+    fn read_error(filepath: &str) -> std::io::ErrorKind {
+        MancalaEndgamesTable::read_from_file(filepath)
+            .expect_err("a bad file was read")
+            .kind()
+    }
+
+    #[test]
+    fn file_rejects_bad_headers() {
+        let filepath = temporary_filepath("endgames-bad-headers");
+        calculated(6).write_to_file(&filepath).unwrap();
+        let bytes = std::fs::read(&filepath).unwrap();
+        let entries_start = super::FILE_HEADER_SIZE;
+
+        // magic, version, max stones, checksum, an entry
+        for (offset, what) in [
+            (0, "magic"),
+            (8, "version"),
+            (22, "max stones"),
+            (34, "checksum"),
+            (entries_start + 10, "entry"),
+        ] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] ^= 1;
+            std::fs::write(&filepath, &corrupted).unwrap();
+            assert_eq!(
+                read_error(&filepath),
+                std::io::ErrorKind::InvalidData,
+                "{}",
+                what
+            );
+        }
+
+        // a mapped file isn't checksummed, but the header is still checked
+        for offset in [0, 8, 22] {
+            let mut corrupted = bytes.clone();
+            corrupted[offset] ^= 1;
+            std::fs::write(&filepath, &corrupted).unwrap();
+            assert!(MancalaEndgamesTable::map_file(&filepath).is_err());
+        }
+
+        for len in [0, entries_start - 1, bytes.len() - 1] {
+            std::fs::write(&filepath, &bytes[..len]).unwrap();
+            assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+            assert!(MancalaEndgamesTable::map_file(&filepath).is_err());
+        }
+
+        std::fs::write(&filepath, &bytes).unwrap();
+        assert!(MancalaEndgamesTable::read_from_file(&filepath).is_ok());
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    // every game state the table covers, with either player to move, looked up in each of tables
+    fn assert_same_lookups(max_stones: u32, tables: &[(&str, MancalaEndgamesTable)]) {
+        let (expected_name, expected) = &tables[0];
+        let mut index = 0;
+        for num_stones in 0..=max_stones {
+            for _ in 0..MancalaEndgamesTable::num_game_states_full_board(num_stones) {
+                let mut game_state = expected.get_game_state(index, num_stones);
+                // the rest of the stones are in the stores, unevenly so the stores count towards the value
+                let stored = MancalaGameState::TOTAL_STONES - num_stones;
+                game_state.set_pit(MancalaGameState::PLAYER_1_STORE, stored / 3);
+                game_state.set_pit(MancalaGameState::PLAYER_2_STORE, stored - stored / 3);
+                for turn in [true, false] {
+                    game_state.set_turn(turn);
+                    let expected_value = expected.lookup(&game_state);
+                    assert!(expected_value.is_some());
+                    for (name, table) in &tables[1..] {
+                        assert_eq!(
+                            table.lookup(&game_state),
+                            expected_value,
+                            "{} and {} differ for {:?}",
+                            name,
+                            expected_name,
+                            game_state
+                        );
+                    }
+                }
+                index += 1;
+            }
+        }
+    }
+
+    #[test]
+    fn mapped_lookups_match_in_memory() {
+        let filepath = temporary_filepath("endgames-mapped");
+        let in_memory = calculated(6);
+        in_memory.write_to_file(&filepath).unwrap();
+        assert_same_lookups(
+            6,
+            &[
+                ("in memory", in_memory),
+                ("mapped", MancalaEndgamesTable::map_file(&filepath).unwrap()),
+            ],
+        );
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn compressed_lookups_match_in_memory() {
+        let filepath = temporary_filepath("endgames-compressed");
+        let in_memory = calculated(6);
+        // blocks much smaller than the table, so lookups cross plenty of them
+        in_memory.write_compressed_file(&filepath, 100, 3).unwrap();
+        let compressed = MancalaEndgamesTable::open_compressed_file(&filepath).unwrap();
+
+        // converting back gives the same file as the table it was written from
+        let raw_filepath = temporary_filepath("endgames-compressed-raw");
+        let in_memory_filepath = temporary_filepath("endgames-compressed-in-memory");
+        compressed.write_to_file(&raw_filepath).unwrap();
+        in_memory.write_to_file(&in_memory_filepath).unwrap();
+        assert_eq!(
+            std::fs::read(&raw_filepath).unwrap(),
+            std::fs::read(&in_memory_filepath).unwrap()
+        );
+
+        assert_same_lookups(
+            6,
+            &[
+                ("in memory", in_memory),
+                ("compressed", compressed),
+                (
+                    "mapped",
+                    MancalaEndgamesTable::map_file(&raw_filepath).unwrap(),
+                ),
+            ],
+        );
+
+        // a raw file isn't a compressed one
+        assert!(MancalaEndgamesTable::open_compressed_file(&raw_filepath).is_err());
+
+        for filepath in [filepath, raw_filepath, in_memory_filepath] {
+            std::fs::remove_file(&filepath).unwrap();
+        }
+    }
+}
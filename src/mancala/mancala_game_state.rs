@@ -1,17 +1,75 @@
 #![allow(dead_code)]
 
-use crate::minimax::GameState;
+use crate::mancala::MancalaUndo;
+use crate::minimax::{GameState, MakeUnmakeGameState};
+use std::hash::{Hash, Hasher};
 
-#[derive(Copy, Clone, Debug, Hash, Eq, PartialEq)]
+#[derive(Copy, Clone, Debug)]
 pub struct MancalaGameState {
     // number of stones in each slot
-    pub pits: [u32; Self::TOTAL_PITS],
+    pits: [u32; Self::TOTAL_PITS],
     // who's turn is it next
-    pub turn: bool,
-    pub game_over: bool,
+    turn: bool,
+    game_over: bool,
+    // zobrist key, kept up to date by every move
+    // the fields above are private so they can only change through something which updates the key too
+    key: u64,
+}
+
+// the key is worked out from the other fields, so comparing and hashing it as well would add nothing
+impl PartialEq for MancalaGameState {
+    fn eq(&self, other: &Self) -> bool {
+        self.pits == other.pits && self.turn == other.turn && self.game_over == other.game_over
+    }
+}
+
+impl Eq for MancalaGameState {}
+
+impl Hash for MancalaGameState {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.pits.hash(state);
+        self.turn.hash(state);
+        self.game_over.hash(state);
+    }
+}
+
+// one random number for every number of stones in every pit, plus one each for the turn and the game being over
+// a game state's key is all of its numbers xor-ed together, so changing a pit only needs two xors
+const ZOBRIST_PITS: [[u64; MancalaGameState::TOTAL_STONES as usize + 1];
+    MancalaGameState::TOTAL_PITS] = zobrist_pits();
+const ZOBRIST_PLAYER_2: u64 = split_mix(u64::MAX);
+const ZOBRIST_GAME_OVER: u64 = split_mix(u64::MAX - 1);
+
+// a well mixed pseudo-random number for each seed, usable in constants
+const fn split_mix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9E3779B97F4A7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+    z ^ (z >> 31)
+}
+
+const fn zobrist_pits(
+) -> [[u64; MancalaGameState::TOTAL_STONES as usize + 1]; MancalaGameState::TOTAL_PITS] {
+    let mut table =
+        [[0; MancalaGameState::TOTAL_STONES as usize + 1]; MancalaGameState::TOTAL_PITS];
+    let mut seed = 0;
+    let mut pit = 0;
+    while pit < MancalaGameState::TOTAL_PITS {
+        let mut stones = 0;
+        while stones <= MancalaGameState::TOTAL_STONES as usize {
+            table[pit][stones] = split_mix(seed);
+            seed += 1;
+            stones += 1;
+        }
+        pit += 1;
+    }
+    table
 }
 
 impl GameState<MancalaGameState> for MancalaGameState {
+    // the index of the pit to sow from
+    type Move = usize;
+
     // fn heuristic(&self) -> i32 {
     //     let mut value =
     //         self.pits[Self::PLAYER_1_STORE] as i32 - self.pits[Self::PLAYER_2_STORE] as i32;
@@ -50,65 +108,132 @@ impl GameState<MancalaGameState> for MancalaGameState {
     ) -> &'a Vec<MancalaGameState> {
         children_cache.clear();
         let children: &mut Vec<MancalaGameState> = children_cache;
-        let pit_offset;
-        let players_store;
-        let opponents_store;
+        let (players_store, opponents_store) = self.stores();
+
+        let (ordered_moves, num_moves) = self.ordered_moves_array();
+        for &player_move in ordered_moves.iter().take(num_moves) {
+            let mut child = *self;
+            child.sow(player_move, players_store, opponents_store);
+            children.push(child);
+        }
 
+        children
+    }
+
+    fn legal_moves(&self) -> Vec<usize> {
+        self.get_valid_moves()
+    }
+
+    fn apply(&self, player_move: usize) -> MancalaGameState {
+        let (players_store, opponents_store) = self.stores();
+
+        let mut child = *self;
+        child.sow(player_move, players_store, opponents_store);
+        child
+    }
+
+    fn get_children_moves(&self, moves: &mut Vec<usize>) {
+        moves.clear();
+        let (ordered_moves, num_moves) = self.ordered_moves_array();
+        moves.extend_from_slice(&ordered_moves[..num_moves]);
+    }
+
+    // moves which give another turn come first, as in get_children
+    fn move_priority(&self, player_move: usize) -> i32 {
+        let (players_store, _) = self.stores();
+        (self.pits[player_move] as usize == players_store - player_move) as i32
+    }
+
+    const MOVE_INDICES: usize = Self::TOTAL_PITS;
+
+    fn move_index(player_move: usize) -> usize {
+        player_move
+    }
+
+    fn zobrist_key(&self) -> u64 {
+        self.key
+    }
+
+    // the stores and the turn, which almost always differ between two game states whose keys collide
+    fn verification_tag(&self) -> u32 {
+        self.pits[Self::PLAYER_1_STORE]
+            | (self.pits[Self::PLAYER_2_STORE] << 8)
+            | ((self.turn as u32) << 16)
+    }
+
+    fn get_children_cache(&self) -> Vec<MancalaGameState> {
+        Vec::with_capacity(Self::PITS_PER_SIDE)
+    }
+
+    // the board seen from the other side, so the canonical form always has player 1 to move
+    // the rules don't depend on which player is which so the mirror plays the same with the score negated
+    fn canonical_form(&self) -> Option<MancalaGameState> {
         if self.turn == Self::PLAYER_1 {
-            pit_offset = 0;
-            players_store = Self::PLAYER_1_STORE;
-            opponents_store = Self::PLAYER_2_STORE;
+            None
         } else {
-            pit_offset = Self::PLAYER_1_STORE + 1;
-            players_store = Self::PLAYER_2_STORE;
-            opponents_store = Self::PLAYER_1_STORE;
+            Some(self.swap_players())
         }
+    }
 
-        // loop through each possible move, add it to the list is valid
-        let mut moves = [0; Self::PITS_PER_SIDE];
-        let mut move_index = 0;
-        for player_move in 0..Self::PITS_PER_SIDE {
-            if self.pits[pit_offset + player_move] > 0 {
-                moves[move_index] = pit_offset + player_move;
-                move_index += 1;
-            }
+    // stones in a store never leave it, so at best a player gets every stone still in play
+    fn value_bounds(&self) -> (i32, i32) {
+        let mut stones_in_play = 0;
+        for i in Self::PLAYER_1_PITS.chain(Self::PLAYER_2_PITS) {
+            stones_in_play += self.pits[i] as i32;
         }
+        let store_difference = self.heuristic();
 
-        // loop through each valid move, if we would get another turn, put it to the start of the list
-        let mut ordered_moves = [0; Self::PITS_PER_SIDE];
-        let mut start_index = 0;
-        let mut end_index = move_index;
-        for i in 0..move_index {
-            let player_move = moves[i];
-            if self.pits[player_move] as usize == players_store - player_move {
-                ordered_moves[start_index] = player_move;
-                start_index += 1;
-            } else {
-                ordered_moves[end_index - 1] = player_move;
-                end_index -= 1;
-            }
-        }
+        (
+            store_difference - stones_in_play,
+            store_difference + stones_in_play,
+        )
+    }
+}
 
-        for i in 0..move_index {
-            let player_move = ordered_moves[i];
-            let mut child = *self;
-            child.make_move(player_move, players_store, opponents_store);
-            children.push(child);
-        }
+impl MakeUnmakeGameState for MancalaGameState {
+    type Undo = MancalaUndo;
 
-        children
+    fn ordered_moves(&self, moves: &mut Vec<usize>) {
+        self.get_children_moves(moves);
     }
 
-    fn get_children_cache(&self) -> Vec<MancalaGameState> {
-        Vec::with_capacity(Self::PITS_PER_SIDE)
+    fn make_move(&mut self, player_move: usize) -> MancalaUndo {
+        let (players_store, opponents_store) = self.stores();
+        self.sow(player_move, players_store, opponents_store)
+    }
+
+    // undo the steps of sow in reverse order
+    fn unmake_move(&mut self, undo: MancalaUndo) {
+        if let Some(pits) = undo.pits_before_game_over {
+            self.pits = pits;
+            self.game_over = false;
+        }
+
+        self.turn = undo.turn;
+        let (players_store, opponents_store) = self.stores();
+
+        self.pits[players_store] -= undo.captured_stones;
+        self.pits[undo.capture_pit] += undo.captured_stones;
+
+        // take one stone back from each pit sown into
+        let mut current_pit = undo.player_move + 1;
+        for _ in 0..undo.stones {
+            if current_pit % Self::TOTAL_PITS == opponents_store {
+                current_pit += 1;
+            }
+            self.pits[current_pit % Self::TOTAL_PITS] -= 1;
+            current_pit += 1;
+        }
+        self.pits[undo.player_move] = undo.stones;
+        self.key = undo.key;
     }
 }
 
 impl MancalaGameState {
     pub const PITS_PER_SIDE: usize = 6;
     pub const PITS_NO_STORES: usize = Self::PITS_PER_SIDE * 2;
-    const TOTAL_PITS: usize = Self::PITS_PER_SIDE * 2 + 2;
-    const STONES_PER_PIT: u32 = 4;
+    pub const TOTAL_PITS: usize = Self::PITS_PER_SIDE * 2 + 2;
+    pub const STONES_PER_PIT: u32 = 4;
     pub const PLAYER_1_STORE: usize = Self::PITS_PER_SIDE;
     pub const PLAYER_2_STORE: usize = Self::PITS_PER_SIDE * 2 + 1;
 
@@ -125,19 +250,81 @@ impl MancalaGameState {
     const WIN_VALUE: i32 = Self::TOTAL_STONES as i32 + 2;
 
     pub fn new() -> MancalaGameState {
-        MancalaGameState {
-            pits: [0; Self::TOTAL_PITS],
-            turn: Self::PLAYER_1,
-            game_over: false,
-        }
+        MancalaGameState::from_pits([0; Self::TOTAL_PITS], Self::PLAYER_1, false)
     }
 
     pub fn default() -> MancalaGameState {
-        MancalaGameState {
-            pits: Self::DEFAULT_STARTING_POSITION,
-            turn: Self::PLAYER_1,
-            game_over: false,
+        MancalaGameState::from_pits(Self::DEFAULT_STARTING_POSITION, Self::PLAYER_1, false)
+    }
+
+    pub fn from_pits(
+        pits: [u32; Self::TOTAL_PITS],
+        turn: bool,
+        game_over: bool,
+    ) -> MancalaGameState {
+        let mut game_state = MancalaGameState {
+            pits,
+            turn,
+            game_over,
+            key: 0,
+        };
+        game_state.update_key();
+        game_state
+    }
+
+    // the same position with the players' sides of the board and the turn swapped
+    pub fn swap_players(&self) -> MancalaGameState {
+        let mut pits = [0; Self::TOTAL_PITS];
+        for (pit, &stones) in self.pits.iter().enumerate() {
+            pits[(pit + Self::PLAYER_1_STORE + 1) % Self::TOTAL_PITS] = stones;
+        }
+        MancalaGameState::from_pits(pits, !self.turn, self.game_over)
+    }
+
+    pub fn pits(&self) -> &[u32; Self::TOTAL_PITS] {
+        &self.pits
+    }
+
+    // true if it's player 1's turn
+    pub fn turn(&self) -> bool {
+        self.turn
+    }
+
+    // work the key out from scratch
+    fn update_key(&mut self) {
+        let mut key = 0;
+        for (pit, &stones) in self.pits.iter().enumerate() {
+            key ^= ZOBRIST_PITS[pit][stones as usize];
+        }
+        if self.turn != Self::PLAYER_1 {
+            key ^= ZOBRIST_PLAYER_2;
+        }
+        if self.game_over {
+            key ^= ZOBRIST_GAME_OVER;
         }
+        self.key = key;
+    }
+
+    // change the number of stones in a pit, keeping the key up to date
+    pub fn set_pit(&mut self, pit: usize, stones: u32) {
+        self.key ^= ZOBRIST_PITS[pit][self.pits[pit] as usize] ^ ZOBRIST_PITS[pit][stones as usize];
+        self.pits[pit] = stones;
+    }
+
+    fn switch_turn(&mut self) {
+        self.turn = !self.turn;
+        self.key ^= ZOBRIST_PLAYER_2;
+    }
+
+    pub fn set_turn(&mut self, turn: bool) {
+        if self.turn != turn {
+            self.switch_turn();
+        }
+    }
+
+    fn set_game_over(&mut self) {
+        self.game_over = true;
+        self.key ^= ZOBRIST_GAME_OVER;
     }
 
     pub fn pretty_print(&self) {
@@ -195,17 +382,23 @@ impl MancalaGameState {
 
         // assume only player one or player two can have 0 stones (which is true in valid game play)
         if player_one_stones == 0 {
-            self.pits[Self::PLAYER_2_STORE] += player_two_stones;
+            self.set_pit(
+                Self::PLAYER_2_STORE,
+                self.pits[Self::PLAYER_2_STORE] + player_two_stones,
+            );
             for i in Self::PLAYER_2_PITS {
-                self.pits[i] = 0;
+                self.set_pit(i, 0);
             }
-            self.game_over = true;
+            self.set_game_over();
         } else if player_two_stones == 0 {
-            self.pits[Self::PLAYER_1_STORE] += player_one_stones;
+            self.set_pit(
+                Self::PLAYER_1_STORE,
+                self.pits[Self::PLAYER_1_STORE] + player_one_stones,
+            );
             for i in Self::PLAYER_1_PITS {
-                self.pits[i] = 0;
+                self.set_pit(i, 0);
             }
-            self.game_over = true;
+            self.set_game_over();
         }
     }
 
@@ -213,7 +406,7 @@ impl MancalaGameState {
     fn move_stones(&mut self, player_move: usize, opponents_store: usize) -> usize {
         // take stones out of the chosen pit
         let number_of_stones: u32 = self.pits[player_move];
-        self.pits[player_move] = 0;
+        self.set_pit(player_move, 0);
 
         // add the remaining stones to each pit (except the opponent's store)
         let mut current_pit = player_move + 1;
@@ -222,7 +415,8 @@ impl MancalaGameState {
             if current_pit % Self::TOTAL_PITS == opponents_store {
                 current_pit += 1;
             }
-            self.pits[current_pit % Self::TOTAL_PITS] += 1;
+            let pit = current_pit % Self::TOTAL_PITS;
+            self.set_pit(pit, self.pits[pit] + 1);
             current_pit += 1;
         }
 
@@ -230,14 +424,31 @@ impl MancalaGameState {
         (current_pit - 1) % Self::TOTAL_PITS
     }
 
-    pub fn make_move(&mut self, player_move: usize, players_store: usize, opponents_store: usize) {
+    // sow the stones from player_move and carry out the rest of the move
+    // returns what's needed to take the move back, see MakeUnmakeGameState
+    fn sow(
+        &mut self,
+        player_move: usize,
+        players_store: usize,
+        opponents_store: usize,
+    ) -> MancalaUndo {
+        let mut undo = MancalaUndo {
+            player_move,
+            stones: self.pits[player_move],
+            turn: self.turn,
+            key: self.key,
+            capture_pit: 0,
+            captured_stones: 0,
+            pits_before_game_over: None,
+        };
+
         let final_pit = self.move_stones(player_move, opponents_store);
 
         let mut capture_occurred = false;
 
         // if another turn is not granted (see rules)
         if final_pit != players_store {
-            self.turn = !self.turn;
+            self.switch_turn();
 
             // if a capture occurs (see capturing rules)
             if final_pit < players_store
@@ -248,10 +459,13 @@ impl MancalaGameState {
                 // capture stones in the opposite pit
                 let opposite_pit = Self::TOTAL_PITS - 2 - final_pit;
                 let stones_to_capture = self.pits[opposite_pit];
-                self.pits[opposite_pit] = 0;
+                self.set_pit(opposite_pit, 0);
 
                 // move those stones to the player's store
-                self.pits[players_store] += stones_to_capture;
+                self.set_pit(players_store, self.pits[players_store] + stones_to_capture);
+
+                undo.capture_pit = opposite_pit;
+                undo.captured_stones = stones_to_capture;
             }
         }
 
@@ -265,14 +479,65 @@ impl MancalaGameState {
             || player_move == Self::PLAYER_1_STORE - 1
             || player_move == Self::PLAYER_2_STORE - 1
         {
+            let pits = self.pits;
             self.handle_game_over();
+            if self.game_over {
+                undo.pits_before_game_over = Some(pits);
+            }
+        }
+
+        undo
+    }
+
+    // (player's store, opponent's store) for the player to move
+    fn stores(&self) -> (usize, usize) {
+        if self.turn == Self::PLAYER_1 {
+            (Self::PLAYER_1_STORE, Self::PLAYER_2_STORE)
+        } else {
+            (Self::PLAYER_2_STORE, Self::PLAYER_1_STORE)
         }
     }
 
+    // the valid moves with those giving another turn first, and how many there are
+    fn ordered_moves_array(&self) -> ([usize; Self::PITS_PER_SIDE], usize) {
+        let (players_store, _) = self.stores();
+        let pit_offset = players_store - Self::PITS_PER_SIDE;
+
+        // loop through each possible move, add it to the list is valid
+        let mut moves = [0; Self::PITS_PER_SIDE];
+        let mut move_index = 0;
+        for player_move in 0..Self::PITS_PER_SIDE {
+            if self.pits[pit_offset + player_move] > 0 {
+                moves[move_index] = pit_offset + player_move;
+                move_index += 1;
+            }
+        }
+
+        // loop through each valid move, if we would get another turn, put it to the start of the list
+        let mut ordered_moves = [0; Self::PITS_PER_SIDE];
+        let mut start_index = 0;
+        let mut end_index = move_index;
+        for &player_move in moves.iter().take(move_index) {
+            if self.pits[player_move] as usize == players_store - player_move {
+                ordered_moves[start_index] = player_move;
+                start_index += 1;
+            } else {
+                ordered_moves[end_index - 1] = player_move;
+                end_index -= 1;
+            }
+        }
+
+        (ordered_moves, move_index)
+    }
+
     pub fn generate_children_memory(max_depth: u32) -> Vec<Vec<MancalaGameState>> {
         vec![Vec::with_capacity(Self::PITS_PER_SIDE); (max_depth + 1) as usize]
     }
 
+    pub fn generate_moves_memory(max_depth: u32) -> Vec<Vec<usize>> {
+        vec![Vec::with_capacity(Self::PITS_PER_SIDE); (max_depth + 1) as usize]
+    }
+
     pub fn get_children_naive(&self) -> Vec<MancalaGameState> {
         let mut children = Vec::with_capacity(Self::PITS_PER_SIDE);
 
@@ -294,7 +559,7 @@ impl MancalaGameState {
             let player_move = pit_offset + i;
             if self.pits[player_move] > 0 {
                 let mut child = *self;
-                child.make_move(player_move, players_store, opponents_store);
+                child.sow(player_move, players_store, opponents_store);
                 children.push(child);
             }
         }
@@ -305,13 +570,11 @@ impl MancalaGameState {
     pub fn get_valid_moves(&self) -> Vec<usize> {
         let mut valid_moves = Vec::with_capacity(Self::PITS_PER_SIDE);
 
-        let pit_offset;
-
-        if self.turn == Self::PLAYER_1 {
-            pit_offset = 0;
+        let pit_offset = if self.turn == Self::PLAYER_1 {
+            0
         } else {
-            pit_offset = Self::PLAYER_1_STORE + 1;
-        }
+            Self::PLAYER_1_STORE + 1
+        };
 
         for i in 0..Self::PITS_PER_SIDE {
             let player_move = pit_offset + i;
@@ -323,3 +586,56 @@ impl MancalaGameState {
         valid_moves
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::mancala::MancalaGameState;
+    use crate::minimax::{GameState, MakeUnmakeGameState};
+
+    // the key a game state would have if it was worked out from scratch
+    fn fresh_key(game_state: &MancalaGameState) -> u64 {
+        MancalaGameState::from_pits(
+            *game_state.pits(),
+            game_state.turn(),
+            game_state.is_game_over(),
+        )
+        .zobrist_key()
+    }
+
+    // every move of every position in a game, right up to the end so game over is covered too
+    #[test]
+    fn make_unmake_round_trip() {
+        let mut game_state = MancalaGameState::default();
+        let mut moves = Vec::new();
+        while !game_state.is_game_over() {
+            game_state.ordered_moves(&mut moves);
+            for &player_move in moves.iter() {
+                let mut made = game_state;
+                let undo = made.make_move(player_move);
+                assert_eq!(made, game_state.apply(player_move));
+                assert_eq!(made.zobrist_key(), fresh_key(&made));
+
+                made.unmake_move(undo);
+                assert_eq!(made, game_state);
+                assert_eq!(made.zobrist_key(), game_state.zobrist_key());
+            }
+            game_state = game_state.apply(moves[moves.len() / 2]);
+        }
+    }
+
+    #[test]
+    fn setters_keep_the_key_up_to_date() {
+        let mut game_state = MancalaGameState::default();
+        game_state.set_pit(3, 7);
+        game_state.set_pit(MancalaGameState::PLAYER_2_STORE, 1);
+        assert_eq!(game_state.zobrist_key(), fresh_key(&game_state));
+        game_state.set_turn(false);
+        assert_eq!(game_state.zobrist_key(), fresh_key(&game_state));
+        game_state.set_turn(false);
+        assert_eq!(game_state.zobrist_key(), fresh_key(&game_state));
+        assert_eq!(
+            game_state.swap_players().swap_players().zobrist_key(),
+            game_state.zobrist_key()
+        );
+    }
+}
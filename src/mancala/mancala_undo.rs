@@ -0,0 +1,16 @@
+use crate::mancala::MancalaGameState;
+
+// everything MancalaGameState::make_move changes which can't be worked out from the game state after the move
+#[derive(Copy, Clone, Debug)]
+pub struct MancalaUndo {
+    pub player_move: usize,
+    pub stones: u32,
+    // whose turn it was before the move
+    pub turn: bool,
+    pub key: u64,
+    // the pit captured from and how many stones were taken, 0 if there was no capture
+    pub capture_pit: usize,
+    pub captured_stones: u32,
+    // ending the game sweeps a whole side into a store, so keep the pits as they were just before
+    pub pits_before_game_over: Option<[u32; MancalaGameState::TOTAL_PITS]>,
+}
@@ -0,0 +1,74 @@
+use memmap2::Mmap;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::sync::Arc;
+
+// the entries of an endgames table file, read straight from the file through a memory map
+// pages are only read from disk when a lookup first touches them, and every process mapping the same file
+// shares them in the page cache, so loading is instant and costs no memory of its own
+#[derive(Clone)]
+pub struct MappedEndgamesFile {
+    mmap: Arc<Mmap>,
+    // where the entries start, after the header
+    offset: usize,
+}
+
+impl MappedEndgamesFile {
+    pub fn open(filepath: &str, offset: usize) -> io::Result<Self> {
+        let file = File::open(filepath)?;
+        // the map is only valid while nobody changes the file, which is fine for tables that are written once
+        // (write_to_file replaces a file rather than changing it in place, so a running process keeps the old one)
+        let mmap = unsafe { Mmap::map(&file)? };
+        if mmap.len() < offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "{} bytes is too short for an endgames table header",
+                    mmap.len()
+                ),
+            ));
+        }
+        Ok(MappedEndgamesFile {
+            mmap: Arc::new(mmap),
+            offset,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.mmap.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.mmap.is_empty()
+    }
+
+    pub fn header(&self) -> &[u8] {
+        &self.mmap[..self.offset]
+    }
+
+    pub fn entries(&self) -> &[u8] {
+        &self.mmap[self.offset..]
+    }
+
+    pub fn evaluation(&self, index: usize) -> i8 {
+        self.mmap[self.offset + index] as i8
+    }
+}
+
+impl fmt::Debug for MappedEndgamesFile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedEndgamesFile")
+            .field("bytes", &self.mmap.len())
+            .field("offset", &self.offset)
+            .finish()
+    }
+}
+
+impl PartialEq for MappedEndgamesFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.entries() == other.entries()
+    }
+}
+
+impl Eq for MappedEndgamesFile {}
@@ -1,5 +1,14 @@
 mod mancala_game_state;
 pub use mancala_game_state::MancalaGameState;
 
+mod mancala_undo;
+pub use mancala_undo::MancalaUndo;
+
 mod mancala_endgames_table;
 pub use mancala_endgames_table::MancalaEndgamesTable;
+
+mod mapped_endgames_file;
+pub use mapped_endgames_file::MappedEndgamesFile;
+
+mod compressed_endgames_file;
+pub use compressed_endgames_file::CompressedEndgamesFile;
@@ -0,0 +1,202 @@
+use crate::minimax::solver::reliable_best_child;
+use crate::minimax::transposition_table::TranspositionTableElement;
+use crate::minimax::{GameState, SearchStats};
+use std::cmp::max;
+use std::cmp::min;
+
+// what alphabeta_exact needs from whoever is running it: a transposition table, a way to order children,
+// and to know when to stop
+// Solver runs it with its own table and move ordering heuristics, each LazySmpSolver thread with the shared table,
+// so both searches share one body and can't drift apart
+pub(crate) trait ExactSearch<T: GameState<T>> {
+    // counts this node, true if the search should stop
+    fn out_of_budget(&mut self) -> bool;
+    // set once the search has stopped, any value returned after this is meaningless
+    fn stopped(&self) -> bool;
+    fn stats(&mut self) -> &mut SearchStats;
+
+    fn endgames_lookup(&self, game_state: &T) -> Option<i32>;
+    // game states more than this many plies from the root aren't looked up or stored
+    fn table_max_depth(&self) -> u32;
+    fn table_lookup(&mut self, game_state: &T) -> Option<TranspositionTableElement>;
+    // see TranspositionTable::store, always at depth u32::MAX as the value is exact
+    fn table_store(
+        &mut self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        best_child: Option<usize>,
+        work: u64,
+    );
+
+    // work out the order to search a node's children in, ply is the distance from the root of the search
+    fn order_children(
+        &mut self,
+        game_state: &T,
+        num_children: usize,
+        hash_child: Option<usize>,
+        ply: u32,
+    );
+    // the index of the i-th child to search, in the order order_children worked out for this ply
+    fn child_at(&self, ply: u32, i: usize) -> usize;
+    // the child at child_index caused a cutoff
+    fn record_cutoff(&mut self, ply: u32, child_index: usize);
+}
+
+// alpha-beta without a depth limit, so every bound found is a true bound on the exact value
+// game_state is ply moves from the root, see Solver::alphabeta_with_memory_no_depth_limit
+pub(crate) fn alphabeta_exact<T: GameState<T>, S: ExactSearch<T>>(
+    search: &mut S,
+    game_state: &T,
+    ply: u32,
+    mut alpha: i32,
+    mut beta: i32,
+    game_state_cache: &mut [Vec<T>],
+) -> i32 {
+    let nodes_before = search.stats().nodes;
+    if search.out_of_budget() {
+        return 0;
+    }
+
+    let mut value;
+    let mut hash_child = None;
+    let mut best_child = None;
+
+    // search endgame table first as it has more accurate results
+    if let Some(eval) = search.endgames_lookup(game_state) {
+        search.stats().endgames_table_hits += 1;
+        value = eval;
+        alpha = eval;
+        beta = eval;
+    } else {
+        if ply <= search.table_max_depth() {
+            // only bounds found without a depth limit are exact, so ignore those from depth limited searches
+            search.stats().transposition_table_probes += 1;
+            if let Some(lookup_result) = search.table_lookup(game_state) {
+                search.stats().transposition_table_hits += 1;
+                hash_child = lookup_result
+                    .best_child
+                    .map(|best_child| best_child as usize);
+                if lookup_result.lower_bound.depth == u32::MAX {
+                    if lookup_result.lower_bound.bound >= beta {
+                        return lookup_result.lower_bound.bound;
+                    }
+                    alpha = max(alpha, lookup_result.lower_bound.bound);
+                }
+
+                if lookup_result.upper_bound.depth == u32::MAX {
+                    if lookup_result.upper_bound.bound <= alpha {
+                        return lookup_result.upper_bound.bound;
+                    }
+                    beta = min(beta, lookup_result.upper_bound.bound)
+                }
+            }
+        }
+
+        // split off the data needed (see README for an explanation of this optimisation)
+        let (current_cache, future_cache) = game_state_cache.split_at_mut(1);
+
+        if game_state.is_game_over() {
+            search.stats().leaf_evaluations += 1;
+            value = game_state.heuristic();
+        }
+        // if player 1
+        else if game_state.is_maximising_player() {
+            let mut a = alpha;
+            value = i32::MIN;
+            search.stats().expanded_nodes += 1;
+            let children = game_state.get_children(&mut current_cache[0]);
+            search.order_children(game_state, children.len(), hash_child, ply);
+            for i in 0..children.len() {
+                let child_index = search.child_at(ply, i);
+                let child = &children[child_index];
+                search.stats().children_searched += 1;
+                let child_value = alphabeta_exact(search, child, ply + 1, a, beta, future_cache);
+                if child_value > value {
+                    value = child_value;
+                    best_child = Some(child_index);
+                }
+                if value >= beta {
+                    search.stats().record_cutoff(i);
+                    search.record_cutoff(ply, child_index);
+                    break;
+                }
+                a = max(a, value);
+            }
+        }
+        // if player 2
+        else {
+            let mut b = beta;
+            value = i32::MAX;
+            search.stats().expanded_nodes += 1;
+            let children = game_state.get_children(&mut current_cache[0]);
+            search.order_children(game_state, children.len(), hash_child, ply);
+            for i in 0..children.len() {
+                let child_index = search.child_at(ply, i);
+                let child = &children[child_index];
+                search.stats().children_searched += 1;
+                let child_value = alphabeta_exact(search, child, ply + 1, alpha, b, future_cache);
+                if child_value < value {
+                    value = child_value;
+                    best_child = Some(child_index);
+                }
+                if value <= alpha {
+                    search.stats().record_cutoff(i);
+                    search.record_cutoff(ply, child_index);
+                    break;
+                }
+                b = min(b, value);
+            }
+        }
+    }
+
+    // bounds from an unfinished search would be wrong so don't store them
+    if search.stopped() {
+        return value;
+    }
+
+    if ply <= search.table_max_depth() {
+        // store this in the transposition table
+        search.stats().transposition_table_stores += 1;
+        let work = search.stats().nodes - nodes_before;
+        search.table_store(
+            game_state,
+            value,
+            alpha,
+            beta,
+            reliable_best_child(game_state, value, alpha, beta, best_child),
+            work,
+        );
+    }
+
+    value
+}
+
+// mtdf from root, which is ply moves from the root of the search, using alphabeta_exact
+pub(crate) fn mtdf_exact<T: GameState<T>, S: ExactSearch<T>>(
+    search: &mut S,
+    root: &T,
+    ply: u32,
+    mut guess: i32,
+    game_state_cache: &mut [Vec<T>],
+) -> i32 {
+    let mut beta: i32;
+    let mut lower_bound = i32::MIN;
+    let mut upper_bound = i32::MAX;
+
+    while lower_bound < upper_bound {
+        beta = max(guess, lower_bound + 1);
+        guess = alphabeta_exact(search, root, ply, beta - 1, beta, game_state_cache);
+        if search.stopped() {
+            break;
+        }
+        if guess < beta {
+            upper_bound = guess;
+        } else {
+            lower_bound = guess;
+        }
+    }
+
+    guess
+}
@@ -1,11 +1,76 @@
+use std::collections::hash_map::DefaultHasher;
 use std::fmt::Debug;
-use std::hash::Hash;
+use std::hash::{Hash, Hasher};
 
 // GameState should encode all the specifics of a game and none of the specifics of the solver algorithm
 pub trait GameState<T>: PartialEq + Eq + Hash + Clone + Debug {
+    // whatever a player chooses on their turn, so results can be reported as moves rather than game states
+    type Move: Copy + Eq + Debug;
+
     fn get_children<'a>(&self, children_cache: &'a mut Vec<T>) -> &'a Vec<T>;
     fn get_children_cache(&self) -> Vec<T>;
     fn is_game_over(&self) -> bool;
     fn heuristic(&self) -> i32;
     fn is_maximising_player(&self) -> bool;
+
+    // every move the player to move can make, in no particular order (unlike get_children, which may be ordered)
+    fn legal_moves(&self) -> Vec<Self::Move>;
+    // the game state after making a legal move
+    fn apply(&self, player_move: Self::Move) -> T;
+
+    // ordering hook for move heuristics such as killer moves and history:
+    // the moves leading to each child returned by get_children, in the same order
+    // the default leaves moves empty, which turns those heuristics off for the game
+    fn get_children_moves(&self, moves: &mut Vec<Self::Move>) {
+        moves.clear();
+    }
+    // how good a move looks to the game itself, the heuristics only reorder moves with equal priority
+    // so knowledge which is always right (like extra turns coming first in mancala) isn't overruled
+    fn move_priority(&self, _player_move: Self::Move) -> i32 {
+        0
+    }
+    // number of distinct values move_index can return, for tables indexed by move
+    const MOVE_INDICES: usize = 0;
+    // a small number identifying a move, below MOVE_INDICES
+    fn move_index(_player_move: Self::Move) -> usize {
+        0
+    }
+
+    // the move which leads from this game state to child, None if child isn't one of its children
+    fn move_to(&self, child: &T) -> Option<Self::Move>
+    where
+        T: PartialEq,
+    {
+        self.legal_moves()
+            .into_iter()
+            .find(|&player_move| self.apply(player_move) == *child)
+    }
+
+    // a 64-bit key for the transposition table, games should keep this up to date as moves are made (Zobrist hashing)
+    // rather than hashing the whole game state every time, which is what the default does
+    fn zobrist_key(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    // a small summary of the game state worked out independently of zobrist_key
+    // the key-only transposition table stores it alongside the key to catch most collisions
+    fn verification_tag(&self) -> u32 {
+        0
+    }
+
+    // player-swap symmetry: None if this game state is already in canonical form, otherwise the mirror image of it
+    // with the players swapped, which is in canonical form and whose values are the negation of this one's
+    // the mirror's get_children must give the mirrors of this game state's children in the same order
+    // the transposition table stores game states under their canonical form, so a game state and its mirror share an entry
+    fn canonical_form(&self) -> Option<T> {
+        None
+    }
+
+    // the lowest and highest values the game can still end with from this game state
+    // solvers which only need to know who wins can stop as soon as these settle the outcome
+    fn value_bounds(&self) -> (i32, i32) {
+        (i32::MIN, i32::MAX)
+    }
 }
@@ -0,0 +1,347 @@
+#![allow(dead_code)]
+
+use crate::minimax::exact_search::{mtdf_exact, ExactSearch};
+use crate::minimax::solver::child_order;
+use crate::minimax::transposition_table::TranspositionTableElement;
+use crate::minimax::{
+    CancelHandle, EndgamesTable, GameState, MoveAnalysis, SearchStats, SharedTranspositionTable,
+};
+use std::cmp::max;
+use std::cmp::min;
+use std::cmp::Reverse;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+// Lazy SMP: several threads run the same exact mtdf search from the same root, sharing one transposition table
+// the threads don't coordinate, they simply benefit from the bounds the others leave in the table
+// helper threads start on a different child near the root so they spread out over the tree
+// (after the best child from the table, which every thread tries first)
+// only the main thread's result is used and helpers are stopped as soon as it finishes
+// every stored bound is a true bound on the exact value so the result is the same as the sequential solver's
+pub struct LazySmpSolver<'a, T: GameState<T>, E: EndgamesTable<T>> {
+    start_game_state: T,
+    pub num_threads: usize,
+    // length of the children cache given to each thread, must be at least the length of the longest game
+    pub max_game_length: u32,
+    pub transposition_table: SharedTranspositionTable<T>,
+    pub endgames_table: &'a E,
+    cancel_handle: CancelHandle,
+    // every thread's stats added together, see take_stats
+    stats: Mutex<SearchStats>,
+}
+
+impl<'a, T, E> LazySmpSolver<'a, T, E>
+where
+    T: GameState<T> + Send + Sync,
+    T::Move: Send + Sync,
+    E: EndgamesTable<T> + Sync,
+{
+    // helpers only reorder children this close to the root, deeper down they use the game's own ordering
+    const HELPER_REORDER_PLIES: u32 = 4;
+
+    pub fn new(
+        start_game_state: T,
+        num_threads: usize,
+        max_game_length: u32,
+        max_table_depth: u32,
+        transposition_table_capacity: usize,
+        endgames_table: &'a E,
+    ) -> Self {
+        LazySmpSolver {
+            start_game_state,
+            num_threads: max(num_threads, 1),
+            max_game_length,
+            transposition_table: SharedTranspositionTable::with_capacity(
+                transposition_table_capacity,
+                max_table_depth,
+            ),
+            endgames_table,
+            cancel_handle: CancelHandle::new(),
+            stats: Mutex::new(SearchStats::new()),
+        }
+    }
+
+    // the stats of every search since the last call, from all threads
+    pub fn take_stats(&self) -> SearchStats {
+        std::mem::take(&mut self.stats.lock().unwrap())
+    }
+
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    // parallel equivalent of Solver::mtdf_with_memory_no_depth_limit
+    // returns None if the search was cancelled before it finished
+    pub fn mtdf_with_memory_no_depth_limit(&self, guess: i32) -> Option<i32> {
+        let helpers_stop = CancelHandle::new();
+
+        let value = thread::scope(|scope| {
+            for worker in 1..self.num_threads {
+                let helpers_stop = &helpers_stop;
+                scope.spawn(move || {
+                    LazySmpWorker::new(self, worker, helpers_stop).mtdf(
+                        &self.start_game_state,
+                        0,
+                        guess,
+                    )
+                });
+            }
+
+            let value = LazySmpWorker::new(self, 0, &CancelHandle::new()).mtdf(
+                &self.start_game_state,
+                0,
+                guess,
+            );
+            helpers_stop.cancel();
+            value
+        });
+
+        if self.cancel_handle.is_cancelled() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    fn is_stopped(&self, stop: &CancelHandle) -> bool {
+        stop.is_cancelled() || self.cancel_handle.is_cancelled()
+    }
+
+    // exact value of every move from the start game state, sorted from best to worst for the player to move
+    // unlike the Lazy SMP search, each thread takes the next move nobody has started on and solves it alone,
+    // so the threads search different parts of the tree and only share what the transposition table holds
+    // returns None if the search was cancelled before every move had a value
+    pub fn analyse_moves(&self) -> Option<Vec<MoveAnalysis<T>>> {
+        if self.start_game_state.is_game_over() {
+            return Some(Vec::new());
+        }
+
+        let legal_moves = self.start_game_state.legal_moves();
+        let next_move = AtomicUsize::new(0);
+        let analysis = Mutex::new(Vec::with_capacity(legal_moves.len()));
+        let no_stop = CancelHandle::new();
+
+        thread::scope(|scope| {
+            for _ in 0..min(self.num_threads, legal_moves.len()) {
+                scope.spawn(|| {
+                    // every thread is worker 0 as they aren't searching the same tree
+                    let mut worker = LazySmpWorker::new(self, 0, &no_stop);
+                    loop {
+                        let move_index = next_move.fetch_add(1, Ordering::Relaxed);
+                        let Some(&player_move) = legal_moves.get(move_index) else {
+                            break;
+                        };
+                        let child = self.start_game_state.apply(player_move);
+                        let value = worker.mtdf(&child, 1, 0);
+                        if self.cancel_handle.is_cancelled() {
+                            break;
+                        }
+                        analysis.lock().unwrap().push((
+                            move_index,
+                            MoveAnalysis {
+                                player_move,
+                                child,
+                                value,
+                                loss: 0,
+                            },
+                        ));
+                    }
+                });
+            }
+        });
+
+        if self.cancel_handle.is_cancelled() {
+            return None;
+        }
+
+        // back into the order legal_moves gave them so equal moves are sorted the same way as Solver::analyse_moves
+        let mut analysis = analysis.into_inner().unwrap();
+        analysis.sort_by_key(|(move_index, _)| *move_index);
+        let mut analysis: Vec<MoveAnalysis<T>> =
+            analysis.into_iter().map(|(_, analysis)| analysis).collect();
+        if self.start_game_state.is_maximising_player() {
+            analysis.sort_by_key(|analysis| Reverse(analysis.value));
+        } else {
+            analysis.sort_by_key(|analysis| analysis.value);
+        }
+        let best_value = analysis[0].value;
+        for analysis in analysis.iter_mut() {
+            analysis.loss = (best_value - analysis.value).abs();
+        }
+
+        Some(analysis)
+    }
+}
+
+// one thread's part in a LazySmpSolver search, made once per thread so the children cache is only allocated once
+struct LazySmpWorker<'s, 'a, T: GameState<T>, E: EndgamesTable<T>> {
+    solver: &'s LazySmpSolver<'a, T, E>,
+    worker: usize,
+    stop: &'s CancelHandle,
+    stats: SearchStats,
+    // per ply, the order the current node's children are searched in
+    child_orders: Vec<Vec<usize>>,
+    game_state_cache: Vec<Vec<T>>,
+}
+
+impl<'s, 'a, T, E> LazySmpWorker<'s, 'a, T, E>
+where
+    T: GameState<T> + Send + Sync,
+    T::Move: Send + Sync,
+    E: EndgamesTable<T> + Sync,
+{
+    fn new(solver: &'s LazySmpSolver<'a, T, E>, worker: usize, stop: &'s CancelHandle) -> Self {
+        LazySmpWorker {
+            solver,
+            worker,
+            stop,
+            stats: SearchStats::new(),
+            child_orders: Vec::new(),
+            game_state_cache: vec![
+                solver.start_game_state.get_children_cache();
+                solver.max_game_length as usize + 1
+            ],
+        }
+    }
+
+    // exact value of root, which is ply moves away from the start game state
+    fn mtdf(&mut self, root: &T, ply: u32, guess: i32) -> i32 {
+        // the search borrows the worker mutably, so the cache is lent out for the duration
+        let mut game_state_cache = std::mem::take(&mut self.game_state_cache);
+        let value = mtdf_exact(self, root, ply, guess, &mut game_state_cache);
+        self.game_state_cache = game_state_cache;
+        value
+    }
+}
+
+// the thread's stats go to the solver when it's finished with the worker, see LazySmpSolver::take_stats
+impl<T: GameState<T>, E: EndgamesTable<T>> Drop for LazySmpWorker<'_, '_, T, E> {
+    fn drop(&mut self) {
+        self.solver.stats.lock().unwrap().add(&self.stats);
+    }
+}
+
+impl<T, E> ExactSearch<T> for LazySmpWorker<'_, '_, T, E>
+where
+    T: GameState<T> + Send + Sync,
+    T::Move: Send + Sync,
+    E: EndgamesTable<T> + Sync,
+{
+    fn out_of_budget(&mut self) -> bool {
+        if self.stopped() {
+            return true;
+        }
+        self.stats.nodes += 1;
+        false
+    }
+
+    fn stopped(&self) -> bool {
+        self.solver.is_stopped(self.stop)
+    }
+
+    fn stats(&mut self) -> &mut SearchStats {
+        &mut self.stats
+    }
+
+    fn endgames_lookup(&self, game_state: &T) -> Option<i32> {
+        self.solver.endgames_table.lookup(game_state)
+    }
+
+    fn table_max_depth(&self) -> u32 {
+        self.solver.transposition_table.max_depth
+    }
+
+    fn table_lookup(&mut self, game_state: &T) -> Option<TranspositionTableElement> {
+        self.solver.transposition_table.lookup(game_state)
+    }
+
+    fn table_store(
+        &mut self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        self.solver.transposition_table.store(
+            game_state,
+            value,
+            alpha,
+            beta,
+            u32::MAX,
+            best_child,
+            work,
+        );
+    }
+
+    // helpers start on a different child near the root, after the best child from the table
+    fn order_children(
+        &mut self,
+        _game_state: &T,
+        num_children: usize,
+        hash_child: Option<usize>,
+        ply: u32,
+    ) {
+        let ply = ply as usize;
+        if self.child_orders.len() <= ply {
+            self.child_orders.resize_with(ply + 1, Vec::new);
+        }
+        let offset = if ply < LazySmpSolver::<T, E>::HELPER_REORDER_PLIES as usize {
+            self.worker % num_children
+        } else {
+            0
+        };
+        let order = &mut self.child_orders[ply];
+        order.clear();
+        order.extend(child_order(num_children, hash_child, offset));
+    }
+
+    fn child_at(&self, ply: u32, i: usize) -> usize {
+        self.child_orders[ply as usize][i]
+    }
+
+    fn record_cutoff(&mut self, _ply: u32, _child_index: usize) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mancala::{MancalaEndgamesTable, MancalaGameState};
+    use crate::minimax::{EndgamesTable, GameState, LazySmpSolver, Solver};
+    use std::rc::Rc;
+
+    // a position late enough in the game for an exact solve to be quick
+    fn endgame() -> MancalaGameState {
+        let mut game_state = MancalaGameState::default();
+        loop {
+            let (lower_bound, upper_bound) = game_state.value_bounds();
+            if (upper_bound - lower_bound) / 2 <= 18 {
+                return game_state;
+            }
+            let legal_moves = game_state.legal_moves();
+            game_state = game_state.apply(legal_moves[legal_moves.len() / 2]);
+        }
+    }
+
+    #[test]
+    fn same_value_and_stats_as_solver() {
+        let mut endgames_table = MancalaEndgamesTable::new(8);
+        endgames_table.calculate_endgames();
+        let endgames_table = Rc::new(endgames_table);
+
+        let mut solver = Solver::new(endgame(), 0, 40, 100000, &endgames_table);
+        let mut game_state_cache = MancalaGameState::generate_children_memory(200);
+        let value = solver.mtdf_with_memory_no_depth_limit(0, &mut game_state_cache);
+        let stats = solver.take_stats();
+
+        // one thread searches the same tree as the solver
+        let lazy_smp = LazySmpSolver::new(endgame(), 1, 200, 40, 100000, &*endgames_table);
+        assert_eq!(lazy_smp.mtdf_with_memory_no_depth_limit(0), Some(value));
+        assert_eq!(lazy_smp.take_stats(), stats);
+
+        let lazy_smp = LazySmpSolver::new(endgame(), 3, 200, 40, 100000, &*endgames_table);
+        assert_eq!(lazy_smp.mtdf_with_memory_no_depth_limit(0), Some(value));
+        assert!(lazy_smp.take_stats().nodes > 0);
+    }
+}
@@ -0,0 +1,16 @@
+use crate::minimax::GameState;
+
+// optional extension of GameState for games which can make a move in place and take it back again
+// the solver can then search with a single game state instead of copying every child into the children cache
+// children are made one at a time, so moves after a cutoff are never made at all
+pub trait MakeUnmakeGameState: GameState<Self> + Sized {
+    // whatever is needed to take a move back, returned by make_move and given back to unmake_move
+    type Undo;
+
+    // the legal moves in the order the search should try them, written into moves to avoid allocating
+    // this should match the order of get_children so both kinds of search explore the same tree
+    fn ordered_moves(&self, moves: &mut Vec<Self::Move>);
+    fn make_move(&mut self, player_move: Self::Move) -> Self::Undo;
+    // must be called with the undo of the most recent move still to be taken back
+    fn unmake_move(&mut self, undo: Self::Undo);
+}
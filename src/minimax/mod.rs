@@ -1,11 +1,49 @@
 mod solver;
 pub use solver::Solver;
 
+mod search_result;
+pub use search_result::SearchResult;
+
+mod move_analysis;
+pub use move_analysis::MoveAnalysis;
+
+mod search_stats;
+pub use search_stats::SearchStats;
+
+mod search_limits;
+pub use search_limits::SearchLimits;
+
+mod cancel_handle;
+pub use cancel_handle::CancelHandle;
+
+mod proof_number_search;
+pub use proof_number_search::{Outcome, ProofNumberSearch};
+
+mod monte_carlo_tree_search;
+pub use monte_carlo_tree_search::{MonteCarloTreeSearch, Playout};
+
+mod exact_search;
+
+mod lazy_smp;
+pub use lazy_smp::LazySmpSolver;
+
 mod transposition_table;
-pub use transposition_table::TranspositionTable;
+pub use transposition_table::{load_transposition_table, TableSize, TranspositionTable};
+
+mod replacement_policy;
+pub use replacement_policy::ReplacementPolicy;
+
+mod shared_transposition_table;
+pub use shared_transposition_table::SharedTranspositionTable;
 
 mod game_state;
 pub use game_state::GameState;
 
+mod make_unmake_game_state;
+pub use make_unmake_game_state::MakeUnmakeGameState;
+
 mod endgames_table;
 pub use endgames_table::EndgamesTable;
+
+mod write_file_replacing;
+pub(crate) use write_file_replacing::write_file_replacing;
@@ -0,0 +1,332 @@
+#![allow(dead_code)]
+
+use crate::minimax::{EndgamesTable, GameState, SearchLimits};
+use std::collections::VecDeque;
+use std::time::Instant;
+
+// how a playout picks moves once it leaves the tree
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Playout {
+    Random,
+    // each player takes the child with the best heuristic for them, breaking ties at random
+    Heuristic,
+}
+
+// Monte Carlo tree search with the UCT selection rule
+// rather than searching every move, play many quick games (playouts) and keep statistics for each move tried
+// the tree grows one level each time a playout passes through a leaf, favouring moves with good results so far
+// UCT balances trying the moves which look best (exploitation) against moves tried only a few times (exploration)
+// results are scored from player 1's point of view: 1 for a win, 0.5 for a draw and 0 for a loss
+pub struct MonteCarloTreeSearch<'a, T: GameState<T>, E: EndgamesTable<T>> {
+    pub endgames_table: &'a E,
+    // larger values explore more, sqrt(2) is the textbook choice
+    pub exploration: f64,
+    pub playout: Playout,
+    // the root is always nodes[0]
+    nodes: Vec<MonteCarloNode<T>>,
+    rng: XorShift,
+}
+
+struct MonteCarloNode<T> {
+    game_state: T,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    expanded: bool,
+    visits: u64,
+    total_score: f64,
+}
+
+impl<T> MonteCarloNode<T> {
+    fn new(game_state: T, parent: Option<usize>) -> Self {
+        MonteCarloNode {
+            game_state,
+            parent,
+            children: Vec::new(),
+            expanded: false,
+            visits: 0,
+            total_score: 0.0,
+        }
+    }
+
+    // average score for player 1
+    fn mean_score(&self) -> f64 {
+        if self.visits == 0 {
+            return 0.5;
+        }
+        self.total_score / self.visits as f64
+    }
+}
+
+impl<'a, T: GameState<T>, E: EndgamesTable<T>> MonteCarloTreeSearch<'a, T, E> {
+    // how many levels below the root advance() looks for the new game state
+    // more than 2 because extra turns mean one player can make several moves in a row
+    const REUSE_SEARCH_DEPTH: u32 = 6;
+
+    pub fn new(
+        start_game_state: T,
+        endgames_table: &'a E,
+        exploration: f64,
+        playout: Playout,
+        seed: u64,
+    ) -> Self {
+        MonteCarloTreeSearch {
+            endgames_table,
+            exploration,
+            playout,
+            nodes: vec![MonteCarloNode::new(start_game_state, None)],
+            rng: XorShift::new(seed),
+        }
+    }
+
+    pub fn root(&self) -> &T {
+        &self.nodes[0].game_state
+    }
+
+    pub fn root_visits(&self) -> u64 {
+        self.nodes[0].visits
+    }
+
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // run playouts until the deadline, or until limits.max_nodes playouts have been run
+    // with no limits at all this would never stop, so a single playout is run instead
+    pub fn search(&mut self, limits: &SearchLimits) {
+        let mut playouts = 0;
+        loop {
+            self.iteration();
+            playouts += 1;
+
+            if let Some(max_playouts) = limits.max_nodes {
+                if playouts >= max_playouts {
+                    break;
+                }
+            }
+            if let Some(deadline) = limits.deadline {
+                if Instant::now() >= deadline {
+                    break;
+                }
+            } else if limits.max_nodes.is_none() {
+                break;
+            }
+        }
+    }
+
+    // the most visited child is the most reliable choice, the best mean score can be a lucky few playouts
+    pub fn best_child(&self) -> Option<T> {
+        self.nodes[0]
+            .children
+            .iter()
+            .max_by_key(|&&child| self.nodes[child].visits)
+            .map(|&child| self.nodes[child].game_state.clone())
+    }
+
+    pub fn best_move(&self) -> Option<T::Move> {
+        let best_child = self.best_child()?;
+        self.root().move_to(&best_child)
+    }
+
+    // each of the root's children with its visit count and player 1's average score
+    pub fn child_statistics(&self) -> Vec<(T, u64, f64)> {
+        self.nodes[0]
+            .children
+            .iter()
+            .map(|&child| {
+                let node = &self.nodes[child];
+                (node.game_state.clone(), node.visits, node.mean_score())
+            })
+            .collect()
+    }
+
+    // move the root to a new game state, usually after a move by each player
+    // if the game state is already in the tree, that subtree and its statistics are kept and the rest thrown away
+    pub fn advance(&mut self, game_state: &T) {
+        let mut queue = VecDeque::from([(0, 0)]);
+        while let Some((index, depth)) = queue.pop_front() {
+            if self.nodes[index].game_state == *game_state {
+                self.reroot(index);
+                return;
+            }
+            if depth < Self::REUSE_SEARCH_DEPTH {
+                for &child in self.nodes[index].children.iter() {
+                    queue.push_back((child, depth + 1));
+                }
+            }
+        }
+
+        self.nodes = vec![MonteCarloNode::new(game_state.clone(), None)];
+    }
+
+    // copy the subtree under new_root into a fresh arena, so the old nodes are freed
+    fn reroot(&mut self, new_root: usize) {
+        if new_root == 0 {
+            return;
+        }
+
+        let mut old_nodes: Vec<Option<MonteCarloNode<T>>> = std::mem::take(&mut self.nodes)
+            .into_iter()
+            .map(Some)
+            .collect();
+
+        // (old index, new parent index)
+        let mut queue = VecDeque::from([(new_root, None)]);
+        while let Some((old_index, parent)) = queue.pop_front() {
+            let mut node = old_nodes[old_index].take().unwrap();
+            let new_index = self.nodes.len();
+            for &child in node.children.iter() {
+                queue.push_back((child, Some(new_index)));
+            }
+            node.parent = parent;
+            node.children.clear();
+            self.nodes.push(node);
+            if let Some(parent) = parent {
+                self.nodes[parent].children.push(new_index);
+            }
+        }
+    }
+
+    fn iteration(&mut self) {
+        let leaf = self.select();
+        let node = self.expand(leaf);
+        let score = self.simulate(node);
+        self.backpropagate(node, score);
+    }
+
+    // walk down the tree picking the child with the best UCT value until reaching a node not yet expanded
+    fn select(&self) -> usize {
+        let mut index = 0;
+        while self.nodes[index].expanded && !self.nodes[index].children.is_empty() {
+            let node = &self.nodes[index];
+            let maximising = node.game_state.is_maximising_player();
+            let log_visits = (node.visits.max(1) as f64).ln();
+
+            let mut best_child = node.children[0];
+            let mut best_uct = f64::MIN;
+            for &child in node.children.iter() {
+                let child_node = &self.nodes[child];
+                // always try every child once before revisiting any
+                if child_node.visits == 0 {
+                    best_child = child;
+                    break;
+                }
+                let score = if maximising {
+                    child_node.mean_score()
+                } else {
+                    1.0 - child_node.mean_score()
+                };
+                let uct = score + self.exploration * (log_visits / child_node.visits as f64).sqrt();
+                if uct > best_uct {
+                    best_uct = uct;
+                    best_child = child;
+                }
+            }
+            index = best_child;
+        }
+
+        index
+    }
+
+    // add the children of a leaf to the tree and pick the first to simulate from
+    fn expand(&mut self, index: usize) -> usize {
+        if self.nodes[index].expanded || self.nodes[index].game_state.is_game_over() {
+            return index;
+        }
+
+        let game_state = self.nodes[index].game_state.clone();
+        let mut children_cache = game_state.get_children_cache();
+        for child in game_state.get_children(&mut children_cache) {
+            let child_index = self.nodes.len();
+            self.nodes
+                .push(MonteCarloNode::new(child.clone(), Some(index)));
+            self.nodes[index].children.push(child_index);
+        }
+        self.nodes[index].expanded = true;
+
+        match self.nodes[index].children.first() {
+            Some(&child) => child,
+            None => index,
+        }
+    }
+
+    // play the game out from a node and score the final position
+    // stops early if the endgame table knows the value
+    fn simulate(&mut self, index: usize) -> f64 {
+        let mut game_state = self.nodes[index].game_state.clone();
+        let mut children_cache = game_state.get_children_cache();
+
+        loop {
+            if game_state.is_game_over() {
+                return Self::score(game_state.heuristic());
+            }
+            if let Some(value) = self.endgames_table.lookup(&game_state) {
+                return Self::score(value);
+            }
+
+            let maximising = game_state.is_maximising_player();
+            let children = game_state.get_children(&mut children_cache);
+            let next = match self.playout {
+                Playout::Random => self.rng.below(children.len()),
+                Playout::Heuristic => {
+                    let player_value = |child: &T| {
+                        if maximising {
+                            child.heuristic()
+                        } else {
+                            -child.heuristic()
+                        }
+                    };
+                    let best_value = children.iter().map(player_value).max().unwrap();
+                    let best: Vec<usize> = (0..children.len())
+                        .filter(|&i| player_value(&children[i]) == best_value)
+                        .collect();
+                    best[self.rng.below(best.len())]
+                }
+            };
+            game_state = children[next].clone();
+        }
+    }
+
+    fn score(value: i32) -> f64 {
+        match value {
+            value if value > 0 => 1.0,
+            0 => 0.5,
+            _ => 0.0,
+        }
+    }
+
+    fn backpropagate(&mut self, mut index: usize, score: f64) {
+        loop {
+            let node = &mut self.nodes[index];
+            node.visits += 1;
+            node.total_score += score;
+            match node.parent {
+                Some(parent) => index = parent,
+                None => break,
+            }
+        }
+    }
+}
+
+// small, fast pseudo-random number generator, good enough for choosing playout moves
+struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    fn new(seed: u64) -> Self {
+        // the state must never be zero
+        XorShift { state: seed.max(1) }
+    }
+
+    fn next(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    // uniform in 0..n
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+}
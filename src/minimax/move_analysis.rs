@@ -0,0 +1,11 @@
+use crate::minimax::GameState;
+
+// the value of one move from the solver's starting game state, see Solver::analyse_moves
+// loss is how much worse this move is than the best move for the player to move, so 0 for the best move
+#[derive(Clone, Debug)]
+pub struct MoveAnalysis<T: GameState<T>> {
+    pub player_move: T::Move,
+    pub child: T,
+    pub value: i32,
+    pub loss: i32,
+}
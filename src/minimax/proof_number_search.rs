@@ -0,0 +1,185 @@
+#![allow(dead_code)]
+
+use crate::minimax::{EndgamesTable, GameState};
+
+// the game-theoretic result for player 1 (the maximising player)
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    Win,
+    Draw,
+    Loss,
+    // the search ran out of nodes before proving anything
+    Unknown,
+}
+
+// proof-number search answers a yes/no question about a game state, here "is the value at least threshold?"
+// every node has a proof number (how many leaves still need proving to show the answer is yes)
+// and a disproof number (how many leaves still need disproving to show the answer is no)
+// the search repeatedly expands the most-proving leaf, the one which would change the root's numbers the most,
+// which heads straight for short forced lines instead of searching every move to the same depth
+// win/draw/loss is found with two questions: "value >= 1?" and, if not, "value >= 0?"
+pub struct ProofNumberSearch<'a, T: GameState<T>, E: EndgamesTable<T>> {
+    pub endgames_table: &'a E,
+    // the tree is kept in memory so the search gives up (Outcome::Unknown) once it holds this many nodes
+    pub max_nodes: usize,
+    nodes: Vec<ProofNode<T>>,
+}
+
+struct ProofNode<T> {
+    game_state: T,
+    parent: Option<usize>,
+    children: Vec<usize>,
+    proof: u64,
+    disproof: u64,
+}
+
+impl<'a, T: GameState<T>, E: EndgamesTable<T>> ProofNumberSearch<'a, T, E> {
+    const INFINITY: u64 = u64::MAX;
+
+    pub fn new(endgames_table: &'a E, max_nodes: usize) -> Self {
+        ProofNumberSearch {
+            endgames_table,
+            max_nodes,
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn solve(&mut self, game_state: &T) -> Outcome {
+        match self.prove_at_least(game_state, 1) {
+            Some(true) => Outcome::Win,
+            Some(false) => match self.prove_at_least(game_state, 0) {
+                Some(true) => Outcome::Draw,
+                Some(false) => Outcome::Loss,
+                None => Outcome::Unknown,
+            },
+            None => Outcome::Unknown,
+        }
+    }
+
+    // Some(true) if the game state's value is at least threshold, Some(false) if it is less
+    // None if the question couldn't be answered within max_nodes
+    pub fn prove_at_least(&mut self, game_state: &T, threshold: i32) -> Option<bool> {
+        self.nodes.clear();
+        let (proof, disproof) = self.evaluate(game_state, threshold);
+        self.nodes.push(ProofNode {
+            game_state: game_state.clone(),
+            parent: None,
+            children: Vec::new(),
+            proof,
+            disproof,
+        });
+
+        let mut children_cache = game_state.get_children_cache();
+        while self.nodes[0].proof != 0 && self.nodes[0].disproof != 0 {
+            if self.nodes.len() >= self.max_nodes {
+                return None;
+            }
+
+            let most_proving = self.select_most_proving();
+            self.expand(most_proving, threshold, &mut children_cache);
+            self.update_ancestors(most_proving);
+        }
+
+        Some(self.nodes[0].proof == 0)
+    }
+
+    // number of nodes in the tree built by the last search
+    pub fn tree_size(&self) -> usize {
+        self.nodes.len()
+    }
+
+    // proof and disproof numbers of a newly created node
+    fn evaluate(&self, game_state: &T, threshold: i32) -> (u64, u64) {
+        let value = if game_state.is_game_over() {
+            Some(game_state.heuristic())
+        } else {
+            self.endgames_table.lookup(game_state)
+        };
+
+        let (lower_bound, upper_bound) = match value {
+            Some(value) => (value, value),
+            None => game_state.value_bounds(),
+        };
+
+        if lower_bound >= threshold {
+            (0, Self::INFINITY)
+        } else if upper_bound < threshold {
+            (Self::INFINITY, 0)
+        } else {
+            (1, 1)
+        }
+    }
+
+    // the maximising player only needs one child to prove the answer, the minimising player needs all of them
+    fn select_most_proving(&self) -> usize {
+        let mut index = 0;
+        while !self.nodes[index].children.is_empty() {
+            let node = &self.nodes[index];
+            index = if node.game_state.is_maximising_player() {
+                *node
+                    .children
+                    .iter()
+                    .min_by_key(|&&child| self.nodes[child].proof)
+                    .unwrap()
+            } else {
+                *node
+                    .children
+                    .iter()
+                    .min_by_key(|&&child| self.nodes[child].disproof)
+                    .unwrap()
+            };
+        }
+
+        index
+    }
+
+    fn expand(&mut self, index: usize, threshold: i32, children_cache: &mut Vec<T>) {
+        let game_state = self.nodes[index].game_state.clone();
+        for child in game_state.get_children(children_cache) {
+            let (proof, disproof) = self.evaluate(child, threshold);
+            let child_index = self.nodes.len();
+            self.nodes.push(ProofNode {
+                game_state: child.clone(),
+                parent: Some(index),
+                children: Vec::new(),
+                proof,
+                disproof,
+            });
+            self.nodes[index].children.push(child_index);
+        }
+    }
+
+    fn update_ancestors(&mut self, mut index: usize) {
+        loop {
+            let node = &self.nodes[index];
+            let mut min_proof = Self::INFINITY;
+            let mut min_disproof = Self::INFINITY;
+            let mut sum_proof: u64 = 0;
+            let mut sum_disproof: u64 = 0;
+            for &child in node.children.iter() {
+                min_proof = min_proof.min(self.nodes[child].proof);
+                min_disproof = min_disproof.min(self.nodes[child].disproof);
+                sum_proof = sum_proof.saturating_add(self.nodes[child].proof);
+                sum_disproof = sum_disproof.saturating_add(self.nodes[child].disproof);
+            }
+
+            let (proof, disproof) = if node.game_state.is_maximising_player() {
+                (min_proof, sum_disproof)
+            } else {
+                (sum_proof, min_disproof)
+            };
+
+            let node = &mut self.nodes[index];
+            let unchanged = node.proof == proof && node.disproof == disproof;
+            node.proof = proof;
+            node.disproof = disproof;
+
+            // once solved the subtree is never visited again so its memory could be reused,
+            // but keeping it is simpler and the parent indices stay valid
+            match node.parent {
+                Some(parent) if !unchanged => index = parent,
+                _ => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,11 @@
+// what a fixed-size transposition table does when a new game state maps to a slot which is already in use
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ReplacementPolicy {
+    // always overwrite, recent game states are the most likely to be searched again
+    AlwaysReplace,
+    // only overwrite an entry searched to the same depth or less, deep results are the most expensive to redo
+    // between equal depths (every entry of a search without a depth limit) the one whose search visited more nodes stays
+    DepthPreferred,
+    // buckets of two: a depth-preferred slot, and an always-replace slot which takes whatever it refuses
+    TwoTier,
+}
@@ -0,0 +1,28 @@
+use std::time::{Duration, Instant};
+
+// limits on how long a search may run for, a search with no limits runs until it finishes
+#[derive(Clone, Debug, Default)]
+pub struct SearchLimits {
+    pub deadline: Option<Instant>,
+    pub max_nodes: Option<u64>,
+}
+
+impl SearchLimits {
+    pub fn none() -> Self {
+        SearchLimits::default()
+    }
+
+    pub fn time(duration: Duration) -> Self {
+        SearchLimits {
+            deadline: Some(Instant::now() + duration),
+            max_nodes: None,
+        }
+    }
+
+    pub fn nodes(max_nodes: u64) -> Self {
+        SearchLimits {
+            deadline: None,
+            max_nodes: Some(max_nodes),
+        }
+    }
+}
@@ -0,0 +1,60 @@
+use crate::minimax::{GameState, SearchStats};
+
+// the outcome of a search from the solver's starting game state
+// principal_variation is the line of play where both players make the best move, starting with best_child
+// moves is the same line as the moves played, starting with best_move
+// depth is the depth the search was limited to, or None if the value is exact
+// an incomplete result comes from a search which was stopped early and has no value or moves
+// stats covers the whole search, including finding the principal variation
+#[derive(Clone, Debug)]
+pub struct SearchResult<T: GameState<T>> {
+    pub value: i32,
+    pub depth: Option<u32>,
+    pub best_child: Option<T>,
+    pub best_move: Option<T::Move>,
+    pub principal_variation: Vec<T>,
+    pub moves: Vec<T::Move>,
+    pub complete: bool,
+    pub stats: SearchStats,
+}
+
+impl<T: GameState<T>> SearchResult<T> {
+    pub fn new(
+        value: i32,
+        depth: Option<u32>,
+        start_game_state: &T,
+        principal_variation: Vec<T>,
+        stats: SearchStats,
+    ) -> Self {
+        let mut moves = Vec::with_capacity(principal_variation.len());
+        let mut game_state = start_game_state;
+        for child in principal_variation.iter() {
+            moves.push(game_state.move_to(child).expect("Fatal Error"));
+            game_state = child;
+        }
+
+        SearchResult {
+            value,
+            depth,
+            best_child: principal_variation.first().cloned(),
+            best_move: moves.first().copied(),
+            principal_variation,
+            moves,
+            complete: true,
+            stats,
+        }
+    }
+
+    pub fn incomplete(depth: Option<u32>, stats: SearchStats) -> Self {
+        SearchResult {
+            value: 0,
+            depth,
+            best_child: None,
+            best_move: None,
+            principal_variation: Vec::new(),
+            moves: Vec::new(),
+            complete: false,
+            stats,
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use std::fmt;
+
+// counters filled in by the solver while it searches, used to compare algorithms
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SearchStats {
+    // every call into a search function, including those answered straight from a table
+    pub nodes: u64,
+    // nodes whose value came from the heuristic (depth limit reached or game over)
+    pub leaf_evaluations: u64,
+    // nodes whose children were generated, and how many of those children were searched
+    pub expanded_nodes: u64,
+    pub children_searched: u64,
+    // indexed by the position of the move which caused the cutoff in the child ordering
+    // the minimising player's cutoffs are counted here too, as they would be in negamax
+    pub beta_cutoffs: Vec<u64>,
+    pub transposition_table_probes: u64,
+    pub transposition_table_hits: u64,
+    pub transposition_table_stores: u64,
+    pub endgames_table_hits: u64,
+    // the depth the search was limited to, None if it wasn't or isn't known, see effective_branching_factor
+    pub depth: Option<u32>,
+}
+
+impl SearchStats {
+    pub fn new() -> Self {
+        SearchStats::default()
+    }
+
+    pub fn record_cutoff(&mut self, move_index: usize) {
+        if self.beta_cutoffs.len() <= move_index {
+            self.beta_cutoffs.resize(move_index + 1, 0);
+        }
+        self.beta_cutoffs[move_index] += 1;
+    }
+
+    pub fn total_cutoffs(&self) -> u64 {
+        self.beta_cutoffs.iter().sum()
+    }
+
+    // how many children are actually searched per expanded node, after pruning
+    // the closer this is to 1 the better the move ordering
+    // not the effective branching factor, which is worked out from how the node count grows with depth
+    pub fn average_children_searched(&self) -> f64 {
+        if self.expanded_nodes == 0 {
+            return 0.0;
+        }
+        self.children_searched as f64 / self.expanded_nodes as f64
+    }
+
+    // the branching factor of a uniform tree with as many nodes as this search to the same depth, nodes = ebf^depth
+    // None without a depth, an exact search has no single depth to spread the nodes over
+    pub fn effective_branching_factor(&self) -> Option<f64> {
+        let depth = self.depth.filter(|&depth| depth > 0)?;
+        Some((self.nodes as f64).powf(1.0 / depth as f64))
+    }
+
+    // fraction of cutoffs caused by the first move tried
+    pub fn first_move_cutoff_rate(&self) -> f64 {
+        let total = self.total_cutoffs();
+        if total == 0 {
+            return 0.0;
+        }
+        self.beta_cutoffs[0] as f64 / total as f64
+    }
+
+    pub fn add(&mut self, other: &SearchStats) {
+        self.nodes += other.nodes;
+        self.leaf_evaluations += other.leaf_evaluations;
+        self.expanded_nodes += other.expanded_nodes;
+        self.children_searched += other.children_searched;
+        for (move_index, cutoffs) in other.beta_cutoffs.iter().enumerate() {
+            if self.beta_cutoffs.len() <= move_index {
+                self.beta_cutoffs.resize(move_index + 1, 0);
+            }
+            self.beta_cutoffs[move_index] += cutoffs;
+        }
+        self.transposition_table_probes += other.transposition_table_probes;
+        self.transposition_table_hits += other.transposition_table_hits;
+        self.transposition_table_stores += other.transposition_table_stores;
+        self.endgames_table_hits += other.endgames_table_hits;
+        self.depth = self.depth.max(other.depth);
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "Nodes: {}, Leaves: {}, EBF: {}, Children/node: {:.2}, Cutoffs: {:?} (first move {:.1}%), TT probes/hits/stores: {}/{}/{}, Endgame hits: {}",
+            self.nodes,
+            self.leaf_evaluations,
+            self.effective_branching_factor()
+                .map_or("-".to_string(), |ebf| format!("{:.2}", ebf)),
+            self.average_children_searched(),
+            self.beta_cutoffs,
+            self.first_move_cutoff_rate() * 100.0,
+            self.transposition_table_probes,
+            self.transposition_table_hits,
+            self.transposition_table_stores,
+            self.endgames_table_hits
+        )
+    }
+}
@@ -0,0 +1,211 @@
+use crate::minimax::game_state::GameState;
+use crate::minimax::transposition_table::{TranspositionTable, TranspositionTableElement};
+use crate::minimax::ReplacementPolicy;
+use std::cmp::max;
+use std::sync::Mutex;
+
+// a transposition table which can be read and written by several threads at once
+// it's split into shards, each a TranspositionTable behind its own lock, and a game state always goes to the shard
+// its zobrist key picks, so threads only wait for each other when they happen to want the same shard at the same time
+// every access takes a lock, so lookups return a copy of the element rather than a reference
+pub struct SharedTranspositionTable<T: GameState<T>> {
+    shards: Vec<Mutex<TranspositionTable<T>>>,
+    pub max_depth: u32,
+    // see TranspositionTable::use_canonical_forms, taken from the shards so it's the same for all of them
+    use_canonical_forms: bool,
+}
+
+impl<T: GameState<T>> SharedTranspositionTable<T> {
+    // plenty for the number of threads a desktop has, while each shard is still big enough to be worth having
+    pub const DEFAULT_NUM_SHARDS: usize = 64;
+
+    pub fn with_capacity(capacity: usize, max_depth: u32) -> SharedTranspositionTable<T> {
+        Self::from_shards(Self::DEFAULT_NUM_SHARDS, max_depth, |num_shards| {
+            TranspositionTable::with_capacity(capacity / num_shards, max_depth)
+        })
+    }
+
+    // see TranspositionTable::with_capacity_keys_only
+    pub fn with_capacity_keys_only(capacity: usize, max_depth: u32) -> SharedTranspositionTable<T> {
+        Self::from_shards(Self::DEFAULT_NUM_SHARDS, max_depth, |num_shards| {
+            TranspositionTable::with_capacity_keys_only(capacity / num_shards, max_depth)
+        })
+    }
+
+    // see TranspositionTable::with_size_mb, the megabytes are shared out between the shards
+    pub fn with_size_mb(
+        megabytes: usize,
+        max_depth: u32,
+        policy: ReplacementPolicy,
+    ) -> SharedTranspositionTable<T> {
+        Self::from_shards(Self::DEFAULT_NUM_SHARDS, max_depth, |num_shards| {
+            TranspositionTable::with_size_bytes(
+                megabytes * 1024 * 1024 / num_shards,
+                max_depth,
+                policy,
+            )
+        })
+    }
+
+    // new_shard is given the number of shards and called once for each of them
+    pub fn from_shards(
+        num_shards: usize,
+        max_depth: u32,
+        new_shard: impl Fn(usize) -> TranspositionTable<T>,
+    ) -> SharedTranspositionTable<T> {
+        let num_shards = max(num_shards, 1);
+        let mut shards: Vec<_> = (0..num_shards).map(|_| new_shard(num_shards)).collect();
+        // a game state and its mirror have to go to the same shard, so either every shard uses canonical forms or none
+        let use_canonical_forms = shards.iter().all(|shard| shard.use_canonical_forms);
+        for shard in shards.iter_mut() {
+            shard.use_canonical_forms = use_canonical_forms;
+        }
+        SharedTranspositionTable::<T> {
+            shards: shards.into_iter().map(Mutex::new).collect(),
+            max_depth,
+            use_canonical_forms,
+        }
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    // the game state game_state's entry is kept under, see TranspositionTable::canonical_form
+    fn canonical_form(&self, game_state: &T) -> Option<T> {
+        if self.use_canonical_forms {
+            game_state.canonical_form()
+        } else {
+            None
+        }
+    }
+
+    // canonical is worked out once here and handed to the shard, which would otherwise work it out again
+    fn shard(&self, game_state: &T, canonical: Option<&T>) -> &Mutex<TranspositionTable<T>> {
+        // the shard tables use the low bits of the key to find entries and hash maps also use the high bits,
+        // so pick the shard with bits from the middle to leave every shard a full spread of keys
+        // a game state and its mirror share an entry so they have to go to the same shard
+        let key = canonical.unwrap_or(game_state).zobrist_key();
+        &self.shards[(key >> 32) as usize % self.shards.len()]
+    }
+
+    pub fn lookup(&self, game_state: &T) -> Option<TranspositionTableElement> {
+        let canonical = self.canonical_form(game_state);
+        self.shard(game_state, canonical.as_ref())
+            .lock()
+            .unwrap()
+            .lookup_canonicalised(game_state, canonical.as_ref())
+    }
+
+    // see TranspositionTable::store
+    #[allow(clippy::too_many_arguments)]
+    pub fn store(
+        &self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        let canonical = self.canonical_form(game_state);
+        self.shard(game_state, canonical.as_ref())
+            .lock()
+            .unwrap()
+            .store_canonicalised(
+                game_state,
+                canonical.as_ref(),
+                value,
+                alpha,
+                beta,
+                depth,
+                best_child,
+                work,
+            );
+    }
+
+    // see TranspositionTable::new_generation
+    pub fn new_generation(&self) {
+        for shard in self.shards.iter() {
+            shard.lock().unwrap().new_generation();
+        }
+    }
+
+    // number of game states stored, this takes every lock in turn so it's only a snapshot while threads are searching
+    pub fn len(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap().len())
+            .sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the shards joined back into one table, e.g. to carry on with a single-threaded Solver or write to a file
+    pub fn into_inner(self) -> TranspositionTable<T> {
+        TranspositionTable::merge(
+            self.shards
+                .into_iter()
+                .map(|shard| shard.into_inner().unwrap())
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mancala::MancalaGameState;
+    use crate::minimax::{
+        GameState, ReplacementPolicy, SharedTranspositionTable, TranspositionTable,
+    };
+
+    fn game_states() -> Vec<MancalaGameState> {
+        let mut game_states = vec![MancalaGameState::default()];
+        let mut children_cache = Vec::new();
+        while game_states.len() < 50 {
+            let game_state = game_states[game_states.len() / 2];
+            for child in game_state.get_children(&mut children_cache) {
+                if !game_states.contains(child) {
+                    game_states.push(*child);
+                }
+            }
+        }
+        game_states
+    }
+
+    // see the test of the same name for TranspositionTable, the shard has to be picked by the canonical form too
+    #[test]
+    fn mirrored_game_states_share_an_entry() {
+        let tables: [SharedTranspositionTable<MancalaGameState>; 2] = [
+            SharedTranspositionTable::with_capacity(1000, u32::MAX),
+            SharedTranspositionTable::with_size_mb(1, u32::MAX, ReplacementPolicy::TwoTier),
+        ];
+        for table in tables {
+            for game_state in game_states() {
+                table.store(&game_state, 7, 0, 5, u32::MAX, None, 1);
+                let mirrored_element = table.lookup(&game_state.swap_players()).unwrap();
+                assert_eq!(mirrored_element.lower_bound.bound, i32::MIN);
+                assert_eq!(mirrored_element.upper_bound.bound, -7);
+            }
+        }
+    }
+
+    // one shard without canonical forms turns them off for all of them, or mirrors could end up in different shards
+    #[test]
+    fn canonical_forms_off_in_every_shard() {
+        let table = SharedTranspositionTable::from_shards(8, u32::MAX, |_| {
+            let mut shard = TranspositionTable::new(u32::MAX);
+            shard.use_canonical_forms = false;
+            shard
+        });
+        for game_state in game_states() {
+            table.store(&game_state, 7, 0, 5, u32::MAX, None, 1);
+            assert!(table.lookup(&game_state).is_some());
+            assert!(table.lookup(&game_state.swap_players()).is_none());
+        }
+        assert!(!table.into_inner().use_canonical_forms);
+    }
+}
@@ -1,15 +1,84 @@
 #![allow(dead_code)]
 
-use crate::minimax::{EndgamesTable, GameState, TranspositionTable};
+use crate::minimax::exact_search::{alphabeta_exact, mtdf_exact, ExactSearch};
+use crate::minimax::transposition_table::TranspositionTableElement;
+use crate::minimax::{
+    CancelHandle, EndgamesTable, GameState, MakeUnmakeGameState, MoveAnalysis, SearchLimits,
+    SearchResult, SearchStats, TranspositionTable,
+};
 use std::cmp::max;
 use std::cmp::min;
+use std::cmp::Reverse;
 use std::rc::Rc;
+use std::time::{Instant, SystemTime};
+use tracing::info;
+
+// indices of the children in the order to search them
+// first_child (usually the best child from the transposition table) comes first, then the rest in the game's order,
+// starting from rotation and wrapping around
+pub(crate) fn child_order(
+    num_children: usize,
+    first_child: Option<usize>,
+    rotation: usize,
+) -> impl Iterator<Item = usize> {
+    let first_child = first_child.filter(|&first_child| first_child < num_children);
+    first_child.into_iter().chain(
+        (0..num_children)
+            .map(move |i| (i + rotation) % num_children)
+            .filter(move |&i| Some(i) != first_child),
+    )
+}
+
+// the best child is only worth remembering if it's known to be at least as good as the rest
+// when every child failed low (high for the minimising player) the values are only bounds and any of them could be best
+pub(crate) fn reliable_best_child<T: GameState<T>>(
+    game_state: &T,
+    value: i32,
+    alpha: i32,
+    beta: i32,
+    best_child: Option<usize>,
+) -> Option<usize> {
+    let reliable = if game_state.is_maximising_player() {
+        value > alpha
+    } else {
+        value < beta
+    };
+    if reliable {
+        best_child
+    } else {
+        None
+    }
+}
 
 pub struct Solver<'a, T: GameState<T>, E: EndgamesTable<T>> {
     start_game_state: T,
     pub max_depth: u32,
     pub transposition_table: TranspositionTable<T>,
     pub endgames_table: &'a Rc<E>,
+    // number of nodes visited since the limits were set, counted against SearchLimits::max_nodes
+    pub nodes: u64,
+    // filled in by every search, see take_stats
+    pub stats: SearchStats,
+    // try the best child stored in the transposition table first, turn off to compare
+    pub transposition_table_move_ordering: bool,
+    // among moves of equal GameState::move_priority, try the moves which recently caused cutoffs at the same ply
+    // (killer moves), then the moves which have caused the most cutoffs anywhere, weighted by depth (history heuristic)
+    // both need GameState::get_children_moves, and are off by default
+    // in mancala killer moves make every search bigger, as the game's own order is already good and a move which
+    // refuted one position rarely refutes its neighbours, they're kept for games without such a good order
+    // (see results/killer_history_benchmark.log)
+    pub killer_moves_enabled: bool,
+    pub history_heuristic_enabled: bool,
+    killer_moves: Vec<[Option<T::Move>; 2]>,
+    // indexed by GameState::move_index
+    history: Vec<u64>,
+    // per ply: the order the current node's children are searched in, and the moves leading to them
+    child_orders: Vec<Vec<usize>>,
+    children_moves: Vec<Vec<T::Move>>,
+    limits: SearchLimits,
+    cancel_handle: CancelHandle,
+    // set once the limits have been exceeded or the search cancelled, any value returned after this is meaningless
+    stopped: bool,
 }
 
 impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
@@ -19,20 +88,217 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         max_table_depth: u32,
         transposition_table_capacity: usize,
         endgames_table: &'a Rc<E>,
+    ) -> Self {
+        Self::with_transposition_table(
+            start_game_state,
+            max_depth,
+            TranspositionTable::with_capacity(transposition_table_capacity, max_table_depth),
+            endgames_table,
+        )
+    }
+
+    // a solver which starts from an existing table, e.g. one loaded with TranspositionTable::read_from_file
+    pub fn with_transposition_table(
+        start_game_state: T,
+        max_depth: u32,
+        transposition_table: TranspositionTable<T>,
+        endgames_table: &'a Rc<E>,
     ) -> Self {
         Solver {
             start_game_state: start_game_state.clone(),
             max_depth,
-            transposition_table: TranspositionTable::with_capacity(
-                transposition_table_capacity,
-                max_table_depth,
-            ),
-            endgames_table: endgames_table,
+            transposition_table,
+            endgames_table,
+            nodes: 0,
+            stats: SearchStats::new(),
+            transposition_table_move_ordering: true,
+            killer_moves_enabled: false,
+            history_heuristic_enabled: false,
+            killer_moves: Vec::new(),
+            history: vec![0; T::MOVE_INDICES],
+            child_orders: Vec::new(),
+            children_moves: Vec::new(),
+            limits: SearchLimits::none(),
+            cancel_handle: CancelHandle::new(),
+            stopped: false,
+        }
+    }
+
+    // move the root on to game_state, e.g. after the moves of a game, keeping the transposition table
+    // its bounds don't depend on the root so they stay valid, and positions searched last time are often searched again
+    pub fn advance(&mut self, game_state: &T) {
+        self.start_game_state = game_state.clone();
+        self.transposition_table.new_generation();
+        // killer moves are kept per ply, which now means different positions
+        self.killer_moves.clear();
+    }
+
+    pub fn set_limits(&mut self, limits: SearchLimits) {
+        self.limits = limits;
+        self.nodes = 0;
+        self.stopped = false;
+    }
+
+    // hand this to another thread to stop searches on this solver
+    pub fn cancel_handle(&self) -> CancelHandle {
+        self.cancel_handle.clone()
+    }
+
+    // share one handle between several solvers so they can all be stopped at once
+    pub fn set_cancel_handle(&mut self, cancel_handle: CancelHandle) {
+        self.cancel_handle = cancel_handle;
+    }
+
+    // statistics gathered since the last call to take_stats
+    pub fn take_stats(&mut self) -> SearchStats {
+        std::mem::take(&mut self.stats)
+    }
+
+    pub fn is_stopped(&self) -> bool {
+        self.stopped || self.cancel_handle.is_cancelled()
+    }
+
+    // forget the killer moves and history, e.g. before searching an unrelated game state
+    pub fn clear_move_ordering_heuristics(&mut self) {
+        self.killer_moves.clear();
+        self.history.iter_mut().for_each(|count| *count = 0);
+    }
+
+    // work out the order to search a node's children in and leave it in child_orders[ply]
+    // the best child from the transposition table comes first, then the rest by the game's move priority,
+    // then killer moves, then history
+    // moves are the moves leading to each child if the caller has them, otherwise they come from the game state
+    // ply is the distance from the root of the search, so the killer moves mean the same thing in every kind of search
+    fn order_children(
+        &mut self,
+        game_state: &T,
+        num_children: usize,
+        hash_child: Option<usize>,
+        ply: usize,
+        moves: Option<&[T::Move]>,
+    ) {
+        if self.child_orders.len() <= ply {
+            self.child_orders.resize_with(ply + 1, Vec::new);
+            self.children_moves.resize_with(ply + 1, Vec::new);
+        }
+        if self.killer_moves.len() <= ply {
+            self.killer_moves.resize(ply + 1, [None; 2]);
+        }
+
+        let order = &mut self.child_orders[ply];
+        order.clear();
+        order.extend(child_order(num_children, hash_child, 0));
+
+        if !self.killer_moves_enabled && !self.history_heuristic_enabled {
+            return;
+        }
+
+        let children_moves = &mut self.children_moves[ply];
+        match moves {
+            Some(moves) => {
+                children_moves.clear();
+                children_moves.extend_from_slice(moves);
+            }
+            None => game_state.get_children_moves(children_moves),
+        }
+        // the game doesn't support move heuristics
+        if children_moves.len() != num_children {
+            return;
+        }
+
+        let killers = if self.killer_moves_enabled {
+            self.killer_moves[ply]
+        } else {
+            [None; 2]
+        };
+        let history = &self.history;
+        let use_history = self.history_heuristic_enabled;
+        let sorted_from = hash_child.is_some_and(|hash_child| hash_child < num_children) as usize;
+        // sort_by_key is stable so ties keep the game's own order
+        order[sorted_from..].sort_by_key(|&child_index| {
+            let player_move = children_moves[child_index];
+            let priority = game_state.move_priority(player_move);
+            let killer_rank = if killers[0] == Some(player_move) {
+                2
+            } else if killers[1] == Some(player_move) {
+                1
+            } else {
+                0
+            };
+            let history_count = if use_history {
+                history
+                    .get(T::move_index(player_move))
+                    .copied()
+                    .unwrap_or(0)
+            } else {
+                0
+            };
+            Reverse((priority, killer_rank, history_count))
+        });
+    }
+
+    // remember the move which caused a cutoff, child_index as in order_children
+    fn record_cutoff_move(&mut self, ply: usize, child_index: usize, weight: u64) {
+        if !self.killer_moves_enabled && !self.history_heuristic_enabled {
+            return;
+        }
+        let Some(&player_move) = self.children_moves[ply].get(child_index) else {
+            return;
+        };
+
+        // a move which was already tried first needs no help from the killers, and pushing it in front of the
+        // game's own order at other nodes made searches several times bigger
+        let tried_first = self.child_orders[ply].first() == Some(&child_index);
+        if self.killer_moves_enabled && !tried_first {
+            let killers = &mut self.killer_moves[ply];
+            if killers[0] != Some(player_move) {
+                killers[1] = killers[0];
+                killers[0] = Some(player_move);
+            }
+        }
+        if self.history_heuristic_enabled {
+            if let Some(count) = self.history.get_mut(T::move_index(player_move)) {
+                *count += weight;
+            }
+        }
+    }
+
+    // counts this node and checks whether the search has run out of nodes or time or has been cancelled
+    fn out_of_budget(&mut self) -> bool {
+        if self.stopped {
+            return true;
+        }
+
+        if self.cancel_handle.is_cancelled() {
+            self.stopped = true;
+            return true;
+        }
+
+        self.nodes += 1;
+        self.stats.nodes += 1;
+        if let Some(max_nodes) = self.limits.max_nodes {
+            if self.nodes > max_nodes {
+                self.stopped = true;
+            }
         }
+        // reading the clock is slow compared to visiting a node so only check it every so often
+        if let Some(deadline) = self.limits.deadline {
+            if self.nodes.is_multiple_of(1024) && Instant::now() >= deadline {
+                self.stopped = true;
+            }
+        }
+
+        self.stopped
     }
 
-    pub fn minimax(&self, game_state: &T, depth: u32, game_state_cache: &mut [Vec<T>]) -> i32 {
+    pub fn minimax(&mut self, game_state: &T, depth: u32, game_state_cache: &mut [Vec<T>]) -> i32 {
+        // the value is meaningless once stopped, callers should check is_stopped()
+        if self.out_of_budget() {
+            return 0;
+        }
+
         if depth == 0 || game_state.is_game_over() {
+            self.stats.leaf_evaluations += 1;
             return game_state.heuristic();
         }
 
@@ -44,14 +310,18 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         // if player 1
         if game_state.is_maximising_player() {
             value = i32::MIN;
+            self.stats.expanded_nodes += 1;
             for child in game_state.get_children(&mut current_cache[0]) {
+                self.stats.children_searched += 1;
                 value = max(value, self.minimax(child, depth - 1, remaining_cache));
             }
         }
         // if player 2
         else {
             value = i32::MAX;
+            self.stats.expanded_nodes += 1;
             for child in game_state.get_children(&mut current_cache[0]) {
+                self.stats.children_searched += 1;
                 value = min(value, self.minimax(child, depth - 1, remaining_cache));
             }
         }
@@ -118,14 +388,19 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
     // if we ever find that player 2 can make a move which results in an evaluation less than alpha then we can immediately discard this branch and return
     // vice-versa for player 1 and beta
     pub fn alphabeta(
-        &self,
+        &mut self,
         game_state: &T,
         depth: u32,
         mut alpha: i32,
         mut beta: i32,
         game_state_cache: &mut [Vec<T>],
     ) -> i32 {
+        if self.out_of_budget() {
+            return 0;
+        }
+
         if depth == 0 || game_state.is_game_over() {
+            self.stats.leaf_evaluations += 1;
             return game_state.heuristic();
         }
 
@@ -137,12 +412,19 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         // if player 1
         if game_state.is_maximising_player() {
             value = i32::MIN;
-            for child in game_state.get_children(&mut current_cache[0]) {
+            self.stats.expanded_nodes += 1;
+            for (i, child) in game_state
+                .get_children(&mut current_cache[0])
+                .iter()
+                .enumerate()
+            {
+                self.stats.children_searched += 1;
                 value = max(
                     value,
                     self.alphabeta(child, depth - 1, alpha, beta, remaining_cache),
                 );
                 if value >= beta {
+                    self.stats.record_cutoff(i);
                     break;
                 }
                 alpha = max(alpha, value);
@@ -151,12 +433,19 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         // if player 2
         else {
             value = i32::MAX;
-            for child in game_state.get_children(&mut current_cache[0]) {
+            self.stats.expanded_nodes += 1;
+            for (i, child) in game_state
+                .get_children(&mut current_cache[0])
+                .iter()
+                .enumerate()
+            {
+                self.stats.children_searched += 1;
                 value = min(
                     value,
                     self.alphabeta(child, depth - 1, alpha, beta, remaining_cache),
                 );
                 if value <= alpha {
+                    self.stats.record_cutoff(i);
                     break;
                 }
                 beta = min(beta, value);
@@ -173,7 +462,7 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
     // calling mtdf multiple times we can narrow down the true result to a specific value
     // NOTE: mtdf only gives major speed-up when using a transposition table and iterative deepening
     pub fn mtdf_no_memory(
-        &self,
+        &mut self,
         mut guess: i32,
         depth: u32,
         game_state_cache: &mut [Vec<T>],
@@ -191,6 +480,9 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
                 beta,
                 game_state_cache,
             );
+            if self.is_stopped() {
+                break;
+            }
             if guess < beta {
                 upper_bound = guess;
             } else {
@@ -206,14 +498,23 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         &mut self,
         game_state: &T,
         depth: u32,
+        ply: u32,
         mut alpha: i32,
         mut beta: i32,
         game_state_cache: &mut [Vec<T>],
     ) -> i32 {
+        let nodes_before = self.stats.nodes;
+        if self.out_of_budget() {
+            return 0;
+        }
+
         let mut value;
+        let mut hash_child = None;
+        let mut best_child = None;
 
         // search endgame table first as it has more accurate results
         if let Some(eval) = self.endgames_table.lookup(game_state) {
+            self.stats.endgames_table_hits += 1;
             value = eval;
             alpha = eval;
             beta = eval;
@@ -221,7 +522,14 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             // opening table
             if self.max_depth - depth <= self.transposition_table.max_depth {
                 // if we have previously explored this node
+                self.stats.transposition_table_probes += 1;
                 if let Some(lookup_result) = self.transposition_table.lookup(game_state) {
+                    self.stats.transposition_table_hits += 1;
+                    if self.transposition_table_move_ordering {
+                        hash_child = lookup_result
+                            .best_child
+                            .map(|best_child| best_child as usize);
+                    }
                     // these values are only valid if we reached this position at an equal or deeper depth
                     if lookup_result.lower_bound.depth >= depth {
                         if lookup_result.lower_bound.bound >= beta {
@@ -243,18 +551,39 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             let (current_cache, future_cache) = game_state_cache.split_at_mut(1);
 
             if depth == 0 || game_state.is_game_over() {
+                self.stats.leaf_evaluations += 1;
                 value = game_state.heuristic();
             }
             // if player 1
             else if game_state.is_maximising_player() {
                 let mut a = alpha;
                 value = i32::MIN;
-                for child in game_state.get_children(&mut current_cache[0]) {
-                    value = max(
-                        value,
-                        self.alphabeta_with_memory(child, depth - 1, a, beta, future_cache),
+                self.stats.expanded_nodes += 1;
+                let children = game_state.get_children(&mut current_cache[0]);
+                self.order_children(game_state, children.len(), hash_child, ply as usize, None);
+                for i in 0..children.len() {
+                    let child_index = self.child_orders[ply as usize][i];
+                    let child = &children[child_index];
+                    self.stats.children_searched += 1;
+                    let child_value = self.alphabeta_with_memory(
+                        child,
+                        depth - 1,
+                        ply + 1,
+                        a,
+                        beta,
+                        future_cache,
                     );
+                    if child_value > value {
+                        value = child_value;
+                        best_child = Some(child_index);
+                    }
                     if value >= beta {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            child_index,
+                            depth as u64 * depth as u64,
+                        );
                         break;
                     }
                     a = max(a, value);
@@ -264,12 +593,32 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             else {
                 let mut b = beta;
                 value = i32::MAX;
-                for child in game_state.get_children(&mut current_cache[0]) {
-                    value = min(
-                        value,
-                        self.alphabeta_with_memory(child, depth - 1, alpha, b, future_cache),
+                self.stats.expanded_nodes += 1;
+                let children = game_state.get_children(&mut current_cache[0]);
+                self.order_children(game_state, children.len(), hash_child, ply as usize, None);
+                for i in 0..children.len() {
+                    let child_index = self.child_orders[ply as usize][i];
+                    let child = &children[child_index];
+                    self.stats.children_searched += 1;
+                    let child_value = self.alphabeta_with_memory(
+                        child,
+                        depth - 1,
+                        ply + 1,
+                        alpha,
+                        b,
+                        future_cache,
                     );
+                    if child_value < value {
+                        value = child_value;
+                        best_child = Some(child_index);
+                    }
                     if value <= alpha {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            child_index,
+                            depth as u64 * depth as u64,
+                        );
                         break;
                     }
                     b = min(b, value);
@@ -277,26 +626,23 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             }
         }
 
+        // bounds from an unfinished search would be wrong so don't store them
+        if self.stopped {
+            return value;
+        }
+
         if self.max_depth - depth <= self.transposition_table.max_depth {
             // store this in the transposition table
-            let transposition_table_element = self.transposition_table.get(game_state);
-            // fail low: we have a new upper bound
-            if value <= alpha {
-                transposition_table_element.upper_bound.bound = value;
-                transposition_table_element.upper_bound.depth = depth;
-            }
-            // accurate value for alpha (won't happen in a zero window)
-            if value > alpha && value < beta {
-                transposition_table_element.lower_bound.bound = value;
-                transposition_table_element.lower_bound.depth = depth;
-                transposition_table_element.upper_bound.bound = value;
-                transposition_table_element.upper_bound.depth = depth;
-            }
-            // fail high: we have a new lower bound
-            if value >= beta {
-                transposition_table_element.lower_bound.bound = value;
-                transposition_table_element.lower_bound.depth = depth;
-            }
+            self.stats.transposition_table_stores += 1;
+            self.transposition_table.store(
+                game_state,
+                value,
+                alpha,
+                beta,
+                depth,
+                reliable_best_child(game_state, value, alpha, beta, best_child),
+                self.stats.nodes - nodes_before,
+            );
         }
 
         value
@@ -317,10 +663,14 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             guess = self.alphabeta_with_memory(
                 &self.start_game_state.clone(),
                 depth,
+                0,
                 beta - 1,
                 beta,
                 game_state_cache,
             );
+            if self.stopped {
+                break;
+            }
             if guess < beta {
                 upper_bound = guess;
             } else {
@@ -331,45 +681,159 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         guess
     }
 
-    // alpha-beta pruning optimisation with a transposition table to store previously explored game states
-    pub fn alphabeta_with_memory_no_depth_limit(
+    // aspiration windows: guess that the value will be close to the previous iteration's value
+    // and search with a narrow window around it, which prunes far more than the full window
+    // if the value lands outside the window (fail low/high) widen that side and search again
+    // the window doubles on each re-search so a bad guess costs only a few extra searches
+    // returns the value and the number of re-searches needed
+    pub fn aspiration_with_memory(
+        &mut self,
+        guess: i32,
+        depth: u32,
+        window: i32,
+        game_state_cache: &mut [Vec<T>],
+    ) -> (i32, u32) {
+        let mut delta = max(window, 1);
+        let mut alpha = guess.saturating_sub(delta);
+        let mut beta = guess.saturating_add(delta);
+        let mut re_searches = 0;
+
+        loop {
+            let value = self.alphabeta_with_memory(
+                &self.start_game_state.clone(),
+                depth,
+                0,
+                alpha,
+                beta,
+                game_state_cache,
+            );
+            if self.stopped {
+                return (value, re_searches);
+            }
+
+            delta = delta.saturating_mul(2);
+            // fail low: the value is at most value
+            if value <= alpha && alpha > i32::MIN {
+                alpha = value.saturating_sub(delta);
+            }
+            // fail high: the value is at least value
+            else if value >= beta && beta < i32::MAX {
+                beta = value.saturating_add(delta);
+            } else {
+                return (value, re_searches);
+            }
+            re_searches += 1;
+        }
+    }
+
+    // principal variation search (also known as NegaScout)
+    // assumes the first child is the best move, so searches it with the full window
+    // every other child is searched with a zero-window just to prove it is no better than the first
+    // if that proof fails the child is re-searched with the full window to find its actual value
+    // with good move ordering most proofs succeed and zero-window searches are much cheaper than full ones
+    // uses the transposition table and endgame table in the same way as alphabeta_with_memory
+    pub fn pvs_with_memory(
         &mut self,
         game_state: &T,
         depth: u32,
+        ply: u32,
         mut alpha: i32,
         mut beta: i32,
         game_state_cache: &mut [Vec<T>],
     ) -> i32 {
+        let nodes_before = self.stats.nodes;
+        if self.out_of_budget() {
+            return 0;
+        }
+
         let mut value;
+        let mut hash_child = None;
+        let mut best_child = None;
 
         // search endgame table first as it has more accurate results
         if let Some(eval) = self.endgames_table.lookup(game_state) {
+            self.stats.endgames_table_hits += 1;
             value = eval;
             alpha = eval;
             beta = eval;
         } else {
+            // opening table
+            if self.max_depth - depth <= self.transposition_table.max_depth {
+                // if we have previously explored this node
+                self.stats.transposition_table_probes += 1;
+                if let Some(lookup_result) = self.transposition_table.lookup(game_state) {
+                    self.stats.transposition_table_hits += 1;
+                    if self.transposition_table_move_ordering {
+                        hash_child = lookup_result
+                            .best_child
+                            .map(|best_child| best_child as usize);
+                    }
+                    // these values are only valid if we reached this position at an equal or deeper depth
+                    if lookup_result.lower_bound.depth >= depth {
+                        if lookup_result.lower_bound.bound >= beta {
+                            return lookup_result.lower_bound.bound;
+                        }
+                        alpha = max(alpha, lookup_result.lower_bound.bound);
+                    }
+
+                    if lookup_result.upper_bound.depth >= depth {
+                        if lookup_result.upper_bound.bound <= alpha {
+                            return lookup_result.upper_bound.bound;
+                        }
+                        beta = min(beta, lookup_result.upper_bound.bound)
+                    }
+                }
+            }
+
             // split off the data needed (see README for an explanation of this optimisation)
             let (current_cache, future_cache) = game_state_cache.split_at_mut(1);
 
-            if game_state.is_game_over() {
+            if depth == 0 || game_state.is_game_over() {
+                self.stats.leaf_evaluations += 1;
                 value = game_state.heuristic();
             }
             // if player 1
             else if game_state.is_maximising_player() {
                 let mut a = alpha;
                 value = i32::MIN;
-                for child in game_state.get_children(&mut current_cache[0]) {
-                    value = max(
-                        value,
-                        self.alphabeta_with_memory_no_depth_limit(
-                            child,
-                            depth + 1,
-                            a,
-                            beta,
-                            future_cache,
-                        ),
-                    );
+                self.stats.expanded_nodes += 1;
+                let children = game_state.get_children(&mut current_cache[0]);
+                self.order_children(game_state, children.len(), hash_child, ply as usize, None);
+                for i in 0..children.len() {
+                    let child_index = self.child_orders[ply as usize][i];
+                    let child = &children[child_index];
+                    self.stats.children_searched += 1;
+                    let mut child_value;
+                    if i == 0 {
+                        child_value =
+                            self.pvs_with_memory(child, depth - 1, ply + 1, a, beta, future_cache);
+                    } else {
+                        // try to prove this child is no better than a
+                        child_value =
+                            self.pvs_with_memory(child, depth - 1, ply + 1, a, a + 1, future_cache);
+                        // proof failed, find out how much better it is
+                        if child_value > a && child_value < beta {
+                            child_value = self.pvs_with_memory(
+                                child,
+                                depth - 1,
+                                ply + 1,
+                                a,
+                                beta,
+                                future_cache,
+                            );
+                        }
+                    }
+                    if child_value > value {
+                        value = child_value;
+                        best_child = Some(child_index);
+                    }
                     if value >= beta {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            child_index,
+                            depth as u64 * depth as u64,
+                        );
                         break;
                     }
                     a = max(a, value);
@@ -379,18 +843,44 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             else {
                 let mut b = beta;
                 value = i32::MAX;
-                for child in game_state.get_children(&mut current_cache[0]) {
-                    value = min(
-                        value,
-                        self.alphabeta_with_memory_no_depth_limit(
-                            child,
-                            depth + 1,
-                            alpha,
-                            b,
-                            future_cache,
-                        ),
-                    );
+                self.stats.expanded_nodes += 1;
+                let children = game_state.get_children(&mut current_cache[0]);
+                self.order_children(game_state, children.len(), hash_child, ply as usize, None);
+                for i in 0..children.len() {
+                    let child_index = self.child_orders[ply as usize][i];
+                    let child = &children[child_index];
+                    self.stats.children_searched += 1;
+                    let mut child_value;
+                    if i == 0 {
+                        child_value =
+                            self.pvs_with_memory(child, depth - 1, ply + 1, alpha, b, future_cache);
+                    } else {
+                        // try to prove this child is no better than b
+                        child_value =
+                            self.pvs_with_memory(child, depth - 1, ply + 1, b - 1, b, future_cache);
+                        // proof failed, find out how much better it is
+                        if child_value < b && child_value > alpha {
+                            child_value = self.pvs_with_memory(
+                                child,
+                                depth - 1,
+                                ply + 1,
+                                alpha,
+                                b,
+                                future_cache,
+                            );
+                        }
+                    }
+                    if child_value < value {
+                        value = child_value;
+                        best_child = Some(child_index);
+                    }
                     if value <= alpha {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            child_index,
+                            depth as u64 * depth as u64,
+                        );
                         break;
                     }
                     b = min(b, value);
@@ -398,33 +888,234 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
             }
         }
 
-        if depth <= self.transposition_table.max_depth {
+        // bounds from an unfinished search would be wrong so don't store them
+        if self.stopped {
+            return value;
+        }
+
+        if self.max_depth - depth <= self.transposition_table.max_depth {
             // store this in the transposition table
-            let transposition_table_element = self.transposition_table.get(game_state);
-            // fail low: we have a new upper bound
-            if value <= alpha {
-                transposition_table_element.upper_bound.bound = value;
-                transposition_table_element.upper_bound.depth = u32::MAX;
+            self.stats.transposition_table_stores += 1;
+            self.transposition_table.store(
+                game_state,
+                value,
+                alpha,
+                beta,
+                depth,
+                reliable_best_child(game_state, value, alpha, beta, best_child),
+                self.stats.nodes - nodes_before,
+            );
+        }
+
+        value
+    }
+
+    // alpha-beta pruning optimisation with a transposition table to store previously explored game states
+    // ply is the number of moves game_state is from the start of the search, the body is shared with LazySmpSolver
+    pub fn alphabeta_with_memory_no_depth_limit(
+        &mut self,
+        game_state: &T,
+        ply: u32,
+        alpha: i32,
+        beta: i32,
+        game_state_cache: &mut [Vec<T>],
+    ) -> i32 {
+        alphabeta_exact(self, game_state, ply, alpha, beta, game_state_cache)
+    }
+
+    pub fn mtdf_with_memory_no_depth_limit(
+        &mut self,
+        guess: i32,
+        game_state_cache: &mut [Vec<T>],
+    ) -> i32 {
+        let start_game_state = self.start_game_state.clone();
+        mtdf_exact(self, &start_game_state, 0, guess, game_state_cache)
+    }
+
+    // exact solve of the start game state, returning the best move and the line of play which follows from it
+    pub fn solve(&mut self, guess: i32, game_state_cache: &mut [Vec<T>]) -> SearchResult<T> {
+        let value = self.mtdf_with_memory_no_depth_limit(guess, game_state_cache);
+        let principal_variation = self.principal_variation(value, None, game_state_cache);
+        let stats = self.take_stats();
+        if self.stopped {
+            return SearchResult::incomplete(None, stats);
+        }
+
+        SearchResult::new(
+            value,
+            None,
+            &self.start_game_state,
+            principal_variation,
+            stats,
+        )
+    }
+
+    // as above but the value and principal variation are only accurate to the given depth
+    pub fn solve_to_depth(
+        &mut self,
+        guess: i32,
+        depth: u32,
+        game_state_cache: &mut [Vec<T>],
+    ) -> SearchResult<T> {
+        let value = self.mtdf_with_memory(guess, depth, game_state_cache);
+        let principal_variation = self.principal_variation(value, Some(depth), game_state_cache);
+        let mut stats = self.take_stats();
+        stats.depth = Some(depth);
+        if self.stopped {
+            return SearchResult::incomplete(Some(depth), stats);
+        }
+
+        SearchResult::new(
+            value,
+            Some(depth),
+            &self.start_game_state,
+            principal_variation,
+            stats,
+        )
+    }
+
+    // search to increasing depths, starting at 2 and stepping by 2, then finish with an exact solve
+    // each iteration seeds mtdf with the previous value and leaves bounds in the transposition table for the next
+    // depth 0 is skipped as it can't choose a move, so any result returned has a best move (unless the game is over)
+    // returns the result of the last iteration to finish before the limits ran out or the search was cancelled,
+    // None means no iteration completed
+    // game_state_cache must be long enough for the longest possible game as the exact solve has no depth limit
+    pub fn iterative_deepening(
+        &mut self,
+        limits: SearchLimits,
+        game_state_cache: &mut [Vec<T>],
+    ) -> Option<SearchResult<T>> {
+        self.set_limits(limits);
+
+        let mut result: Option<SearchResult<T>> = None;
+        let mut depth = 2;
+        // nodes searched by the previous depth limited iteration, two plies shallower
+        let mut previous_nodes = None;
+        let mut final_iteration = false;
+        while !final_iteration {
+            let guess = result.as_ref().map_or(0, |result| result.value);
+            let now = SystemTime::now();
+
+            let iteration_result = if depth <= self.max_depth {
+                self.solve_to_depth(guess, depth, game_state_cache)
+            } else {
+                final_iteration = true;
+                self.solve(guess, game_state_cache)
+            };
+            if !iteration_result.complete {
+                break;
             }
-            // accurate value for alpha (won't happen in a zero window)
-            if value > alpha && value < beta {
-                transposition_table_element.lower_bound.bound = value;
-                transposition_table_element.lower_bound.depth = u32::MAX;
-                transposition_table_element.upper_bound.bound = value;
-                transposition_table_element.upper_bound.depth = u32::MAX;
+
+            let time = now.elapsed().unwrap();
+            info!(
+                "Value: {}, Depth: {:?}, Time: {}ms",
+                iteration_result.value,
+                iteration_result.depth,
+                time.as_millis()
+            );
+            info!("{}", iteration_result.stats);
+            // how much more each ply cost than the last, the other common measure of the effective branching factor
+            if let (Some(previous_nodes), Some(_)) = (previous_nodes, iteration_result.depth) {
+                info!(
+                    "EBF from the previous iteration: {:.2}",
+                    (iteration_result.stats.nodes as f64 / previous_nodes as f64).sqrt()
+                );
             }
-            // fail high: we have a new lower bound
-            if value >= beta {
-                transposition_table_element.lower_bound.bound = value;
-                transposition_table_element.lower_bound.depth = u32::MAX;
+            previous_nodes = Some(iteration_result.stats.nodes.max(1));
+
+            result = Some(iteration_result);
+            depth += 2;
+        }
+
+        // later searches shouldn't be cut short by these limits
+        self.set_limits(SearchLimits::none());
+
+        result
+    }
+
+    // value of every move from the start game state, sorted from best to worst for the player to move
+    // each child gets its own mtdf search, seeded with the previous child's value as siblings are usually close
+    // depth limits the search as in solve_to_depth (at least one ply is always searched), None gives exact values
+    // returns None if the search was stopped before every move had a value
+    pub fn analyse_moves(
+        &mut self,
+        depth: Option<u32>,
+        game_state_cache: &mut [Vec<T>],
+    ) -> Option<Vec<MoveAnalysis<T>>> {
+        let start_game_state = self.start_game_state.clone();
+        if start_game_state.is_game_over() {
+            return Some(Vec::new());
+        }
+
+        let depth = depth.map(|depth| max(depth, 1));
+        let maximising = start_game_state.is_maximising_player();
+        let legal_moves = start_game_state.legal_moves();
+
+        let mut analysis = Vec::with_capacity(legal_moves.len());
+        let mut guess = 0;
+        for player_move in legal_moves {
+            let child = start_game_state.apply(player_move);
+            guess = self.exact_value(&child, 1, depth, guess, game_state_cache);
+            if self.stopped {
+                return None;
             }
+            analysis.push(MoveAnalysis {
+                player_move,
+                child,
+                value: guess,
+                loss: 0,
+            });
         }
 
-        value
+        // sort_by_key is stable so equal moves stay in the order legal_moves gave them
+        if maximising {
+            analysis.sort_by_key(|analysis| Reverse(analysis.value));
+        } else {
+            analysis.sort_by_key(|analysis| analysis.value);
+        }
+        let best_value = analysis[0].value;
+        for analysis in analysis.iter_mut() {
+            analysis.loss = (best_value - analysis.value).abs();
+        }
+
+        Some(analysis)
     }
 
-    pub fn mtdf_with_memory_no_depth_limit(
+    // zero-window search of a game state which is ply moves away from the start game state
+    // depth is the depth the start game state was searched to (None if there was no depth limit)
+    fn zero_window(
+        &mut self,
+        game_state: &T,
+        ply: u32,
+        depth: Option<u32>,
+        beta: i32,
+        game_state_cache: &mut [Vec<T>],
+    ) -> i32 {
+        match depth {
+            Some(depth) => self.alphabeta_with_memory(
+                game_state,
+                depth - ply,
+                ply,
+                beta - 1,
+                beta,
+                game_state_cache,
+            ),
+            None => self.alphabeta_with_memory_no_depth_limit(
+                game_state,
+                ply,
+                beta - 1,
+                beta,
+                game_state_cache,
+            ),
+        }
+    }
+
+    // mtdf from any game state in the tree rather than only the start game state
+    fn exact_value(
         &mut self,
+        game_state: &T,
+        ply: u32,
+        depth: Option<u32>,
         mut guess: i32,
         game_state_cache: &mut [Vec<T>],
     ) -> i32 {
@@ -434,13 +1125,10 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
 
         while lower_bound < upper_bound {
             beta = max(guess, lower_bound + 1);
-            guess = self.alphabeta_with_memory_no_depth_limit(
-                &self.start_game_state.clone(),
-                0,
-                beta - 1,
-                beta,
-                game_state_cache,
-            );
+            guess = self.zero_window(game_state, ply, depth, beta, game_state_cache);
+            if self.stopped {
+                break;
+            }
             if guess < beta {
                 upper_bound = guess;
             } else {
@@ -451,6 +1139,67 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         guess
     }
 
+    // walk down from the start game state, at each step choosing a child which achieves its parent's value
+    // a zero-window search around the parent's value tells us whether a child is at least as good for the player to move
+    // these searches mostly hit the bounds left in the transposition table by the search which found the value
+    fn principal_variation(
+        &mut self,
+        mut value: i32,
+        depth: Option<u32>,
+        game_state_cache: &mut [Vec<T>],
+    ) -> Vec<T> {
+        let mut principal_variation = Vec::new();
+        let mut game_state = self.start_game_state.clone();
+        let mut children_cache = game_state.get_children_cache();
+        let mut ply = 0;
+
+        while !self.stopped && !game_state.is_game_over() && depth.is_none_or(|depth| ply < depth) {
+            let maximising = game_state.is_maximising_player();
+            let children = game_state.get_children(&mut children_cache).clone();
+
+            let mut best_child = None;
+            for child in children.iter() {
+                let achieves_value = if maximising {
+                    self.zero_window(child, ply + 1, depth, value, game_state_cache) >= value
+                } else {
+                    self.zero_window(child, ply + 1, depth, value + 1, game_state_cache) <= value
+                };
+                if achieves_value {
+                    best_child = Some(child.clone());
+                    break;
+                }
+            }
+
+            // bounds from a depth limited search can disagree between iterations
+            // if no child matched, fall back to finding each child's exact value
+            if best_child.is_none() {
+                let mut best_value = if maximising { i32::MIN } else { i32::MAX };
+                for child in children.iter() {
+                    let child_value =
+                        self.exact_value(child, ply + 1, depth, value, game_state_cache);
+                    if (maximising && child_value > best_value)
+                        || (!maximising && child_value < best_value)
+                    {
+                        best_value = child_value;
+                        best_child = Some(child.clone());
+                    }
+                }
+                value = best_value;
+            }
+
+            match best_child {
+                Some(child) => {
+                    principal_variation.push(child.clone());
+                    game_state = child;
+                    ply += 1;
+                }
+                None => break,
+            }
+        }
+
+        principal_variation
+    }
+
     pub fn test(
         &mut self,
         game_state: &T,
@@ -458,11 +1207,17 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         mut beta: i32,
         game_state_cache: &mut [Vec<T>],
     ) -> i32 {
+        if self.out_of_budget() {
+            return 0;
+        }
+
         // search endgame table first as it has more accurate results
         if let Some(eval) = self.endgames_table.lookup(game_state) {
+            self.stats.endgames_table_hits += 1;
             return eval;
         }
         if game_state.is_game_over() {
+            self.stats.leaf_evaluations += 1;
             return game_state.heuristic();
         }
 
@@ -474,9 +1229,16 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         // if player 1
         if game_state.is_maximising_player() {
             value = i32::MIN;
-            for child in game_state.get_children(&mut current_cache[0]) {
+            self.stats.expanded_nodes += 1;
+            for (i, child) in game_state
+                .get_children(&mut current_cache[0])
+                .iter()
+                .enumerate()
+            {
+                self.stats.children_searched += 1;
                 value = max(value, self.test(child, alpha, beta, future_cache));
                 if value >= beta {
+                    self.stats.record_cutoff(i);
                     break;
                 }
                 alpha = max(alpha, value);
@@ -485,9 +1247,16 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         // if player 2
         else {
             value = i32::MAX;
-            for child in game_state.get_children(&mut current_cache[0]) {
+            self.stats.expanded_nodes += 1;
+            for (i, child) in game_state
+                .get_children(&mut current_cache[0])
+                .iter()
+                .enumerate()
+            {
+                self.stats.children_searched += 1;
                 value = min(value, self.test(child, alpha, beta, future_cache));
                 if value <= alpha {
+                    self.stats.record_cutoff(i);
                     break;
                 }
                 beta = min(beta, value);
@@ -511,6 +1280,214 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
                 beta,
                 game_state_cache,
             );
+            if self.is_stopped() {
+                break;
+            }
+            if guess < beta {
+                upper_bound = guess;
+            } else {
+                lower_bound = guess;
+            }
+        }
+
+        guess
+    }
+}
+
+// searches for games which can make and take back moves in place, see MakeUnmakeGameState
+impl<'a, T: MakeUnmakeGameState, E: EndgamesTable<T>> Solver<'a, T, E> {
+    // same as alphabeta_with_memory but game_state is changed in place and restored before returning
+    // moves_cache plays the part of the children cache, one list of moves per ply
+    // the best move stored in the transposition table is its index in ordered_moves, which matches get_children
+    pub fn alphabeta_make_unmake(
+        &mut self,
+        game_state: &mut T,
+        depth: u32,
+        ply: u32,
+        mut alpha: i32,
+        mut beta: i32,
+        moves_cache: &mut [Vec<T::Move>],
+    ) -> i32 {
+        let nodes_before = self.stats.nodes;
+        if self.out_of_budget() {
+            return 0;
+        }
+
+        let mut value;
+        let mut hash_child = None;
+        let mut best_child = None;
+
+        // search endgame table first as it has more accurate results
+        if let Some(eval) = self.endgames_table.lookup(game_state) {
+            self.stats.endgames_table_hits += 1;
+            value = eval;
+            alpha = eval;
+            beta = eval;
+        } else {
+            // opening table
+            if self.max_depth - depth <= self.transposition_table.max_depth {
+                // if we have previously explored this node
+                self.stats.transposition_table_probes += 1;
+                if let Some(lookup_result) = self.transposition_table.lookup(game_state) {
+                    self.stats.transposition_table_hits += 1;
+                    if self.transposition_table_move_ordering {
+                        hash_child = lookup_result
+                            .best_child
+                            .map(|best_child| best_child as usize);
+                    }
+                    // these values are only valid if we reached this position at an equal or deeper depth
+                    if lookup_result.lower_bound.depth >= depth {
+                        if lookup_result.lower_bound.bound >= beta {
+                            return lookup_result.lower_bound.bound;
+                        }
+                        alpha = max(alpha, lookup_result.lower_bound.bound);
+                    }
+
+                    if lookup_result.upper_bound.depth >= depth {
+                        if lookup_result.upper_bound.bound <= alpha {
+                            return lookup_result.upper_bound.bound;
+                        }
+                        beta = min(beta, lookup_result.upper_bound.bound)
+                    }
+                }
+            }
+
+            // split off the data needed, as with the children cache
+            let (current_cache, future_cache) = moves_cache.split_at_mut(1);
+            let moves = &mut current_cache[0];
+
+            if depth == 0 || game_state.is_game_over() {
+                self.stats.leaf_evaluations += 1;
+                value = game_state.heuristic();
+            }
+            // if player 1
+            else if game_state.is_maximising_player() {
+                let mut a = alpha;
+                value = i32::MIN;
+                self.stats.expanded_nodes += 1;
+                game_state.ordered_moves(moves);
+                self.order_children(
+                    game_state,
+                    moves.len(),
+                    hash_child,
+                    ply as usize,
+                    Some(moves),
+                );
+                for i in 0..moves.len() {
+                    let move_index = self.child_orders[ply as usize][i];
+                    let player_move = moves[move_index];
+                    self.stats.children_searched += 1;
+                    let undo = game_state.make_move(player_move);
+                    let child_value = self.alphabeta_make_unmake(
+                        game_state,
+                        depth - 1,
+                        ply + 1,
+                        a,
+                        beta,
+                        future_cache,
+                    );
+                    game_state.unmake_move(undo);
+                    if child_value > value {
+                        value = child_value;
+                        best_child = Some(move_index);
+                    }
+                    if value >= beta {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            move_index,
+                            depth as u64 * depth as u64,
+                        );
+                        break;
+                    }
+                    a = max(a, value);
+                }
+            }
+            // if player 2
+            else {
+                let mut b = beta;
+                value = i32::MAX;
+                self.stats.expanded_nodes += 1;
+                game_state.ordered_moves(moves);
+                self.order_children(
+                    game_state,
+                    moves.len(),
+                    hash_child,
+                    ply as usize,
+                    Some(moves),
+                );
+                for i in 0..moves.len() {
+                    let move_index = self.child_orders[ply as usize][i];
+                    let player_move = moves[move_index];
+                    self.stats.children_searched += 1;
+                    let undo = game_state.make_move(player_move);
+                    let child_value = self.alphabeta_make_unmake(
+                        game_state,
+                        depth - 1,
+                        ply + 1,
+                        alpha,
+                        b,
+                        future_cache,
+                    );
+                    game_state.unmake_move(undo);
+                    if child_value < value {
+                        value = child_value;
+                        best_child = Some(move_index);
+                    }
+                    if value <= alpha {
+                        self.stats.record_cutoff(i);
+                        self.record_cutoff_move(
+                            ply as usize,
+                            move_index,
+                            depth as u64 * depth as u64,
+                        );
+                        break;
+                    }
+                    b = min(b, value);
+                }
+            }
+        }
+
+        // bounds from an unfinished search would be wrong so don't store them
+        if self.stopped {
+            return value;
+        }
+
+        if self.max_depth - depth <= self.transposition_table.max_depth {
+            // store this in the transposition table
+            self.stats.transposition_table_stores += 1;
+            self.transposition_table.store(
+                game_state,
+                value,
+                alpha,
+                beta,
+                depth,
+                reliable_best_child(game_state, value, alpha, beta, best_child),
+                self.stats.nodes - nodes_before,
+            );
+        }
+
+        value
+    }
+
+    pub fn mtdf_make_unmake(
+        &mut self,
+        mut guess: i32,
+        depth: u32,
+        moves_cache: &mut [Vec<T::Move>],
+    ) -> i32 {
+        let mut game_state = self.start_game_state.clone();
+        let mut beta: i32;
+        let mut lower_bound = i32::MIN;
+        let mut upper_bound = i32::MAX;
+
+        while lower_bound < upper_bound {
+            beta = max(guess, lower_bound + 1);
+            guess =
+                self.alphabeta_make_unmake(&mut game_state, depth, 0, beta - 1, beta, moves_cache);
+            if self.stopped {
+                break;
+            }
             if guess < beta {
                 upper_bound = guess;
             } else {
@@ -521,3 +1498,104 @@ impl<'a, T: GameState<T>, E: EndgamesTable<T>> Solver<'a, T, E> {
         guess
     }
 }
+
+impl<'a, T: GameState<T>, E: EndgamesTable<T>> ExactSearch<T> for Solver<'a, T, E> {
+    fn out_of_budget(&mut self) -> bool {
+        Solver::out_of_budget(self)
+    }
+
+    fn stopped(&self) -> bool {
+        self.stopped
+    }
+
+    fn stats(&mut self) -> &mut SearchStats {
+        &mut self.stats
+    }
+
+    fn endgames_lookup(&self, game_state: &T) -> Option<i32> {
+        self.endgames_table.lookup(game_state)
+    }
+
+    fn table_max_depth(&self) -> u32 {
+        self.transposition_table.max_depth
+    }
+
+    fn table_lookup(&mut self, game_state: &T) -> Option<TranspositionTableElement> {
+        let mut element = self.transposition_table.lookup(game_state)?;
+        if !self.transposition_table_move_ordering {
+            element.best_child = None;
+        }
+        Some(element)
+    }
+
+    fn table_store(
+        &mut self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        self.transposition_table
+            .store(game_state, value, alpha, beta, u32::MAX, best_child, work);
+    }
+
+    fn order_children(
+        &mut self,
+        game_state: &T,
+        num_children: usize,
+        hash_child: Option<usize>,
+        ply: u32,
+    ) {
+        Solver::order_children(
+            self,
+            game_state,
+            num_children,
+            hash_child,
+            ply as usize,
+            None,
+        );
+    }
+
+    fn child_at(&self, ply: u32, i: usize) -> usize {
+        self.child_orders[ply as usize][i]
+    }
+
+    fn record_cutoff(&mut self, ply: u32, child_index: usize) {
+        self.record_cutoff_move(ply as usize, child_index, 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mancala::{MancalaEndgamesTable, MancalaGameState};
+    use crate::minimax::{EndgamesTable, SearchLimits, Solver};
+    use std::rc::Rc;
+
+    fn endgames_table() -> Rc<MancalaEndgamesTable> {
+        let mut endgames_table = MancalaEndgamesTable::new(6);
+        endgames_table.calculate_endgames();
+        Rc::new(endgames_table)
+    }
+
+    // too few nodes to finish the first iteration gives None rather than a result without a move
+    #[test]
+    fn iterative_deepening_always_has_a_move() {
+        let endgames_table = endgames_table();
+        for max_nodes in [0, 5, 50, 500, 5000] {
+            let mut solver =
+                Solver::new(MancalaGameState::default(), 20, 10, 1000, &endgames_table);
+            let mut game_state_cache = MancalaGameState::generate_children_memory(1000);
+            let result =
+                solver.iterative_deepening(SearchLimits::nodes(max_nodes), &mut game_state_cache);
+            // a tiny budget may not finish the first iteration, a reasonable one always does
+            if max_nodes < 500 && result.is_none() {
+                continue;
+            }
+            let result = result.expect("no iteration finished");
+            assert!(result.depth.is_none_or(|depth| depth >= 1));
+            assert!(result.best_move.is_some());
+        }
+    }
+}
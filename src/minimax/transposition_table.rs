@@ -1,36 +1,860 @@
 use crate::minimax::game_state::GameState;
+use crate::minimax::{write_file_replacing, ReplacementPolicy};
+use std::any::type_name;
+use std::cmp::max;
 use std::collections::HashMap;
+use std::fs::File;
+use std::hash::{BuildHasherDefault, Hasher};
+use std::io::{self, BufReader, Read, Seek, Write};
+use std::mem::size_of;
+use tracing::{info, warn};
+
+// file format, all numbers little endian:
+//   magic, version, length and bytes of the game's type name, max_depth,
+//   kind (0 keys, 1 fixed), for a fixed table the policy, bucket size and number of slots,
+//   number of entries, then every entry: slot index (fixed only), key, verification tag,
+//   lower bound and depth, upper bound and depth, best child (u32::MAX for none), work
+// bump the version whenever any of that changes so old files are refused rather than misread
+// 2 added the work, 3 is the first version whose mirrored game states are stored under their canonical form
+const FILE_MAGIC: &[u8; 4] = b"MMTT";
+const FILE_VERSION: u32 = 3;
+const NO_BEST_CHILD: u32 = u32::MAX;
+// bytes written by write_entry, a fixed table's entries also have their slot index in front
+const ENTRY_BYTES: u64 = 36;
+// no game's type name is anywhere near this long, so anything longer is a corrupt file
+const MAX_GAME_NAME_BYTES: u64 = 1024;
 
 #[derive(Clone)]
 pub struct TranspositionTable<T: GameState<T>> {
-    data: HashMap<T, TranspositionTableElement>,
+    data: TranspositionTableData<T>,
     pub max_depth: u32,
+    // bumped whenever the solver moves on to a new root, every entry remembers the generation it was last stored in
+    generation: u16,
+    // store game states under GameState::canonical_form, on by default, games without a symmetry aren't affected
+    pub use_canonical_forms: bool,
+}
+
+// the full game state is the safest key but every probe hashes and compares the whole game state
+// keeping only GameState::zobrist_key is smaller and faster, but two game states can share a key
+// the verification tag makes that very unlikely to go unnoticed, and when it is noticed the old entry is replaced
+// both of those grow without limit, the fixed table is allocated once and replaces entries when it's full
+#[derive(Clone)]
+enum TranspositionTableData<T: GameState<T>> {
+    GameStates(HashMap<T, TranspositionTableElement>),
+    Keys(HashMap<u64, KeyedElement, BuildHasherDefault<KeyHasher>>),
+    Fixed {
+        slots: Vec<FixedSlot>,
+        // slots are grouped into buckets, a game state can only go in the bucket its key picks
+        bucket_size: usize,
+        policy: ReplacementPolicy,
+    },
+}
+
+#[derive(Clone, Debug)]
+struct FixedSlot {
+    used: bool,
+    key: u64,
+    verification_tag: u32,
+    element: TranspositionTableElement,
+}
+
+impl FixedSlot {
+    fn empty() -> Self {
+        FixedSlot {
+            used: false,
+            key: 0,
+            verification_tag: 0,
+            element: TranspositionTableElement::default(),
+        }
+    }
+
+    fn holds(&self, key: u64, verification_tag: u32) -> bool {
+        self.used && self.key == key && self.verification_tag == verification_tag
+    }
+
+    // whether a depth-preferred slot should give way to a result of the given depth and work
+    // entries left over from earlier generations always give way, however deep they were
+    // the work breaks ties between equal depths, which is every entry in a search without a depth limit
+    fn replaceable(&self, depth: u32, work: u32, generation: u16) -> bool {
+        !self.used
+            || self.element.generation != generation
+            || (depth, work) >= (self.element.depth(), self.element.work)
+    }
+
+    fn reset(&mut self, key: u64, verification_tag: u32) {
+        self.used = true;
+        self.key = key;
+        self.verification_tag = verification_tag;
+        self.element = TranspositionTableElement::default();
+    }
+}
+
+#[derive(Clone, Debug)]
+struct KeyedElement {
+    verification_tag: u32,
+    element: TranspositionTableElement,
 }
 
 impl<T: GameState<T>> TranspositionTable<T> {
     pub fn new(max_depth: u32) -> TranspositionTable<T> {
         TranspositionTable::<T> {
-            data: HashMap::new(),
+            data: TranspositionTableData::GameStates(HashMap::new()),
             max_depth,
+            generation: 0,
+            use_canonical_forms: true,
         }
     }
 
     pub fn with_capacity(capacity: usize, max_depth: u32) -> TranspositionTable<T> {
         TranspositionTable::<T> {
-            data: HashMap::with_capacity(capacity),
+            data: TranspositionTableData::GameStates(HashMap::with_capacity(capacity)),
+            max_depth,
+            generation: 0,
+            use_canonical_forms: true,
+        }
+    }
+
+    // a table which stores zobrist keys rather than game states
+    pub fn with_capacity_keys_only(capacity: usize, max_depth: u32) -> TranspositionTable<T> {
+        TranspositionTable::<T> {
+            data: TranspositionTableData::Keys(HashMap::with_capacity_and_hasher(
+                capacity,
+                BuildHasherDefault::default(),
+            )),
+            max_depth,
+            generation: 0,
+            use_canonical_forms: true,
+        }
+    }
+
+    // a table which never uses more than roughly the given number of megabytes
+    // it stores keys like the key-only table, and replaces entries according to policy once it fills up
+    pub fn with_size_mb(
+        megabytes: usize,
+        max_depth: u32,
+        policy: ReplacementPolicy,
+    ) -> TranspositionTable<T> {
+        Self::with_size_bytes(megabytes * 1024 * 1024, max_depth, policy)
+    }
+
+    pub(crate) fn with_size_bytes(
+        bytes: usize,
+        max_depth: u32,
+        policy: ReplacementPolicy,
+    ) -> TranspositionTable<T> {
+        let bucket_size = match policy {
+            ReplacementPolicy::TwoTier => 2,
+            ReplacementPolicy::AlwaysReplace | ReplacementPolicy::DepthPreferred => 1,
+        };
+        let num_buckets = max(bytes / (size_of::<FixedSlot>() * bucket_size), 1);
+
+        TranspositionTable::<T> {
+            data: TranspositionTableData::Fixed {
+                slots: vec![FixedSlot::empty(); num_buckets * bucket_size],
+                bucket_size,
+                policy,
+            },
+            max_depth,
+            generation: 0,
+            use_canonical_forms: true,
+        }
+    }
+
+    pub fn is_keys_only(&self) -> bool {
+        !matches!(self.data, TranspositionTableData::GameStates(_))
+    }
+
+    // number of game states stored, for a fixed table this counts the used slots so is slow
+    pub fn len(&self) -> usize {
+        match &self.data {
+            TranspositionTableData::GameStates(data) => data.len(),
+            TranspositionTableData::Keys(data) => data.len(),
+            TranspositionTableData::Fixed { slots, .. } => {
+                slots.iter().filter(|slot| slot.used).count()
+            }
+        }
+    }
+
+    // the most game states the table can hold, None if it grows as needed
+    pub fn capacity(&self) -> Option<usize> {
+        match &self.data {
+            TranspositionTableData::Fixed { slots, .. } => Some(slots.len()),
+            _ => None,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // the bounds stored for game_state, worked out from those of its canonical form if it has one
+    // a hit moves the entry into the current generation, so entries which keep being used aren't treated as stale
+    pub fn lookup(&mut self, game_state: &T) -> Option<TranspositionTableElement> {
+        let canonical = self.canonical_form(game_state);
+        self.lookup_canonicalised(game_state, canonical.as_ref())
+    }
+
+    // the game state game_state's entry is kept under, None if that's game_state itself
+    pub(crate) fn canonical_form(&self, game_state: &T) -> Option<T> {
+        if self.use_canonical_forms {
+            game_state.canonical_form()
+        } else {
+            None
+        }
+    }
+
+    // lookup for a caller which already has canonical from canonical_form, so it isn't worked out twice
+    pub(crate) fn lookup_canonicalised(
+        &mut self,
+        game_state: &T,
+        canonical: Option<&T>,
+    ) -> Option<TranspositionTableElement> {
+        match canonical {
+            Some(canonical) => self
+                .lookup_exact(canonical)
+                .map(|element| element.negated()),
+            None => self.lookup_exact(game_state).cloned(),
+        }
+    }
+
+    fn lookup_exact(&mut self, game_state: &T) -> Option<&TranspositionTableElement> {
+        let element = match &mut self.data {
+            TranspositionTableData::GameStates(data) => data.get_mut(game_state),
+            TranspositionTableData::Keys(data) => data
+                .get_mut(&game_state.zobrist_key())
+                .filter(|keyed| keyed.verification_tag == game_state.verification_tag())
+                .map(|keyed| &mut keyed.element),
+            TranspositionTableData::Fixed {
+                slots, bucket_size, ..
+            } => {
+                let key = game_state.zobrist_key();
+                let verification_tag = game_state.verification_tag();
+                Self::bucket_mut(slots, *bucket_size, key)
+                    .iter_mut()
+                    .find(|slot| slot.holds(key, verification_tag))
+                    .map(|slot| &mut slot.element)
+            }
+        }?;
+        element.generation = self.generation;
+        Some(element)
+    }
+
+    // record the result of an alpha-beta search of game_state with the given window, see TranspositionTableElement::store
+    // work is the number of nodes the search visited, a fixed table may drop the result rather than replace an
+    // entry which was deeper or took more work to find
+    #[allow(clippy::too_many_arguments)]
+    pub fn store(
+        &mut self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        let canonical = self.canonical_form(game_state);
+        self.store_canonicalised(
+            game_state,
+            canonical.as_ref(),
+            value,
+            alpha,
+            beta,
+            depth,
+            best_child,
+            work,
+        );
+    }
+
+    // store for a caller which already has canonical from canonical_form, see lookup_canonicalised
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn store_canonicalised(
+        &mut self,
+        game_state: &T,
+        canonical: Option<&T>,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        match canonical {
+            // negating the values turns the window around
+            Some(canonical) => self.store_exact(
+                canonical,
+                negate(value),
+                negate(beta),
+                negate(alpha),
+                depth,
+                best_child,
+                work,
+            ),
+            None => self.store_exact(game_state, value, alpha, beta, depth, best_child, work),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn store_exact(
+        &mut self,
+        game_state: &T,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        best_child: Option<usize>,
+        work: u64,
+    ) {
+        let work = u32::try_from(work).unwrap_or(u32::MAX);
+        let generation = self.generation;
+        if let Some(element) = self.get(game_state, depth, work) {
+            element.store(value, alpha, beta, depth, best_child, work);
+            element.generation = generation;
+        }
+    }
+
+    // the slot to store a result of the given depth and work in, None if the policy says to keep what's there
+    #[allow(clippy::too_many_arguments)]
+    fn choose_slot(
+        bucket: &mut [FixedSlot],
+        policy: ReplacementPolicy,
+        key: u64,
+        verification_tag: u32,
+        depth: u32,
+        work: u32,
+        generation: u16,
+    ) -> Option<&mut FixedSlot> {
+        // the same game state always updates its own entry
+        if let Some(index) = bucket
+            .iter()
+            .position(|slot| slot.holds(key, verification_tag))
+        {
+            return Some(&mut bucket[index]);
+        }
+
+        match policy {
+            ReplacementPolicy::AlwaysReplace => Some(&mut bucket[0]),
+            ReplacementPolicy::DepthPreferred => {
+                let slot = &mut bucket[0];
+                if slot.replaceable(depth, work, generation) {
+                    Some(slot)
+                } else {
+                    None
+                }
+            }
+            ReplacementPolicy::TwoTier => {
+                let (depth_preferred, always_replace) = bucket.split_at_mut(1);
+                if depth_preferred[0].replaceable(depth, work, generation) {
+                    // keep the entry being pushed out in the always-replace slot
+                    always_replace[0] = depth_preferred[0].clone();
+                    Some(&mut depth_preferred[0])
+                } else {
+                    Some(&mut always_replace[0])
+                }
+            }
+        }
+    }
+
+    fn bucket(slots: &[FixedSlot], bucket_size: usize, key: u64) -> &[FixedSlot] {
+        let start = (key % (slots.len() / bucket_size) as u64) as usize * bucket_size;
+        &slots[start..start + bucket_size]
+    }
+
+    fn bucket_mut(slots: &mut [FixedSlot], bucket_size: usize, key: u64) -> &mut [FixedSlot] {
+        let start = (key % (slots.len() / bucket_size) as u64) as usize * bucket_size;
+        &mut slots[start..start + bucket_size]
+    }
+
+    // start a new generation, e.g. when the solver moves on to the next position in a game
+    // entries are kept and still used, but a fixed table replaces older ones first and remove_stale can clear them out
+    pub fn new_generation(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn generation(&self) -> u16 {
+        self.generation
+    }
+
+    // drop every entry last stored more than max_age generations ago, returning how many were dropped
+    // for tables which grow without limit, a game moves away from positions it has played past so those entries go to waste
+    // both lookup and store bring an entry into the current generation, so only entries nobody has touched are dropped
+    pub fn remove_stale(&mut self, max_age: u16) -> usize {
+        let generation = self.generation;
+        let stale = |element: &TranspositionTableElement| {
+            generation.wrapping_sub(element.generation) > max_age
+        };
+        let len_before = self.len();
+        match &mut self.data {
+            TranspositionTableData::GameStates(data) => data.retain(|_, element| !stale(element)),
+            TranspositionTableData::Keys(data) => data.retain(|_, keyed| !stale(&keyed.element)),
+            TranspositionTableData::Fixed { slots, .. } => {
+                for slot in slots
+                    .iter_mut()
+                    .filter(|slot| slot.used && stale(&slot.element))
+                {
+                    *slot = FixedSlot::empty();
+                }
+            }
+        }
+        len_before - self.len()
+    }
+
+    // join tables holding different game states into one, as the shards of a SharedTranspositionTable do
+    // they're expected to be the same kind of table, fixed tables give one as big as all of them together
+    pub(crate) fn merge(tables: Vec<TranspositionTable<T>>) -> TranspositionTable<T> {
+        let total_capacity: usize = tables.iter().filter_map(|table| table.capacity()).sum();
+        let mut tables = tables.into_iter();
+        let mut table = tables.next().expect("nothing to merge");
+        if let TranspositionTableData::Fixed { slots, .. } = &mut table.data {
+            let own_slots = std::mem::replace(slots, vec![FixedSlot::empty(); total_capacity]);
+            table.absorb_slots(own_slots);
+        }
+        for other in tables {
+            table.generation = max(table.generation, other.generation);
+            match (&mut table.data, other.data) {
+                (
+                    TranspositionTableData::GameStates(data),
+                    TranspositionTableData::GameStates(other),
+                ) => data.extend(other),
+                (TranspositionTableData::Keys(data), TranspositionTableData::Keys(other)) => {
+                    data.extend(other)
+                }
+                (
+                    TranspositionTableData::Fixed { .. },
+                    TranspositionTableData::Fixed { slots: other, .. },
+                ) => table.absorb_slots(other),
+                _ => panic!("can't merge different kinds of transposition table"),
+            }
+        }
+        table
+    }
+
+    // place the used slots in a fixed table, by its replacement policy so some may be dropped
+    fn absorb_slots(&mut self, other: Vec<FixedSlot>) {
+        let generation = self.generation;
+        if let TranspositionTableData::Fixed {
+            slots,
+            bucket_size,
+            policy,
+        } = &mut self.data
+        {
+            for other_slot in other.into_iter().filter(|slot| slot.used) {
+                let bucket = Self::bucket_mut(slots, *bucket_size, other_slot.key);
+                if let Some(slot) = Self::choose_slot(
+                    bucket,
+                    *policy,
+                    other_slot.key,
+                    other_slot.verification_tag,
+                    other_slot.element.depth(),
+                    other_slot.element.work,
+                    generation,
+                ) {
+                    *slot = other_slot;
+                }
+            }
+        }
+    }
+
+    // save the table so a later run can carry on from it, entries are written by zobrist key
+    // so a table of game states comes back as a key-only table
+    // keys have to mean the same thing in the run which reads the file, which holds for games whose
+    // zobrist keys are built from constants, the DefaultHasher fallback can change between rust versions
+    // the file is replaced in one go, so stopping part way through leaves any earlier file as it was
+    pub fn write_to_file(&self, filepath: &str) -> io::Result<()> {
+        write_file_replacing(filepath, |writer| self.write(writer))
+    }
+
+    fn write(&self, writer: &mut impl Write) -> io::Result<()> {
+        writer.write_all(FILE_MAGIC)?;
+        writer.write_all(&FILE_VERSION.to_le_bytes())?;
+        let game_name = type_name::<T>().as_bytes();
+        writer.write_all(&(game_name.len() as u32).to_le_bytes())?;
+        writer.write_all(game_name)?;
+        writer.write_all(&self.max_depth.to_le_bytes())?;
+
+        match &self.data {
+            TranspositionTableData::GameStates(data) => {
+                writer.write_all(&[0])?;
+                writer.write_all(&(data.len() as u64).to_le_bytes())?;
+                for (game_state, element) in data {
+                    let key = game_state.zobrist_key();
+                    write_entry(writer, key, game_state.verification_tag(), element)?;
+                }
+            }
+            TranspositionTableData::Keys(data) => {
+                writer.write_all(&[0])?;
+                writer.write_all(&(data.len() as u64).to_le_bytes())?;
+                for (key, keyed) in data {
+                    write_entry(writer, *key, keyed.verification_tag, &keyed.element)?;
+                }
+            }
+            TranspositionTableData::Fixed {
+                slots,
+                bucket_size,
+                policy,
+            } => {
+                writer.write_all(&[1, policy_to_byte(*policy)])?;
+                writer.write_all(&(*bucket_size as u32).to_le_bytes())?;
+                writer.write_all(&(slots.len() as u64).to_le_bytes())?;
+                let num_used = slots.iter().filter(|slot| slot.used).count();
+                writer.write_all(&(num_used as u64).to_le_bytes())?;
+                for (index, slot) in slots.iter().enumerate().filter(|(_, slot)| slot.used) {
+                    writer.write_all(&(index as u64).to_le_bytes())?;
+                    write_entry(writer, slot.key, slot.verification_tag, &slot.element)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // load a table saved by write_to_file, a fixed table comes back with the same size and policy
+    // files from another version of the format or another game are refused with InvalidData, as are lengths which
+    // don't fit in the file, so a corrupt file can't make this allocate more than it needs
+    pub fn read_from_file(filepath: &str) -> io::Result<TranspositionTable<T>> {
+        let file = File::open(filepath)?;
+        let file_len = file.metadata()?.len();
+        let mut reader = BufReader::new(file);
+
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != FILE_MAGIC {
+            return Err(invalid_data("not a transposition table file".to_string()));
+        }
+        let version = read_u32(&mut reader)?;
+        if version != FILE_VERSION {
+            return Err(invalid_data(format!(
+                "transposition table file is version {}, expected {}",
+                version, FILE_VERSION
+            )));
+        }
+        let game_name_len = read_u32(&mut reader)? as u64;
+        check_fits(&mut reader, file_len, game_name_len, 1, "game name")?;
+        if game_name_len > MAX_GAME_NAME_BYTES {
+            return Err(invalid_data(format!(
+                "game name of {} bytes is too long",
+                game_name_len
+            )));
+        }
+        let mut game_name = vec![0; game_name_len as usize];
+        reader.read_exact(&mut game_name)?;
+        if game_name != type_name::<T>().as_bytes() {
+            return Err(invalid_data(format!(
+                "transposition table file is for {}, expected {}",
+                String::from_utf8_lossy(&game_name),
+                type_name::<T>()
+            )));
+        }
+        let max_depth = read_u32(&mut reader)?;
+
+        let data = match read_u8(&mut reader)? {
+            0 => {
+                let num_entries = read_u64(&mut reader)?;
+                check_fits(&mut reader, file_len, num_entries, ENTRY_BYTES, "entries")?;
+                let num_entries = num_entries as usize;
+                let mut data =
+                    HashMap::with_capacity_and_hasher(num_entries, BuildHasherDefault::default());
+                for _ in 0..num_entries {
+                    let (key, verification_tag, element) = read_entry(&mut reader)?;
+                    data.insert(
+                        key,
+                        KeyedElement {
+                            verification_tag,
+                            element,
+                        },
+                    );
+                }
+                TranspositionTableData::Keys(data)
+            }
+            1 => {
+                let policy = policy_from_byte(read_u8(&mut reader)?)?;
+                let bucket_size = read_u32(&mut reader)? as usize;
+                let num_slots = read_u64(&mut reader)? as usize;
+                if bucket_size == 0 || num_slots == 0 || !num_slots.is_multiple_of(bucket_size) {
+                    return Err(invalid_data(format!(
+                        "{} slots can't be split into buckets of {}",
+                        num_slots, bucket_size
+                    )));
+                }
+                // unused slots aren't written so the file doesn't limit the size, but the memory has to exist
+                let mut slots = Vec::new();
+                slots.try_reserve_exact(num_slots).map_err(|_| {
+                    invalid_data(format!("can't allocate a table of {} slots", num_slots))
+                })?;
+                slots.resize(num_slots, FixedSlot::empty());
+                let num_entries = read_u64(&mut reader)?;
+                check_fits(
+                    &mut reader,
+                    file_len,
+                    num_entries,
+                    ENTRY_BYTES + 8,
+                    "entries",
+                )?;
+                for _ in 0..num_entries {
+                    let index = read_u64(&mut reader)? as usize;
+                    let (key, verification_tag, element) = read_entry(&mut reader)?;
+                    let slot = slots.get_mut(index).ok_or_else(|| {
+                        invalid_data(format!("slot {} out of range {}", index, num_slots))
+                    })?;
+                    *slot = FixedSlot {
+                        used: true,
+                        key,
+                        verification_tag,
+                        element,
+                    };
+                }
+                TranspositionTableData::Fixed {
+                    slots,
+                    bucket_size,
+                    policy,
+                }
+            }
+            kind => {
+                return Err(invalid_data(format!(
+                    "unknown transposition table kind {}",
+                    kind
+                )))
+            }
+        };
+
+        // generations aren't saved, everything loaded starts off as new
+        Ok(TranspositionTable::<T> {
+            data,
             max_depth,
+            generation: 0,
+            use_canonical_forms: true,
+        })
+    }
+
+    // the element to store a result of the given depth and work for game_state in,
+    // None if it's a fixed table whose policy says to keep what's there
+    fn get(
+        &mut self,
+        game_state: &T,
+        depth: u32,
+        work: u32,
+    ) -> Option<&mut TranspositionTableElement> {
+        match &mut self.data {
+            // TODO avoid cloning here if possible
+            TranspositionTableData::GameStates(data) => Some(
+                data.entry(game_state.clone())
+                    .or_insert(TranspositionTableElement::default()),
+            ),
+            TranspositionTableData::Keys(data) => {
+                let verification_tag = game_state.verification_tag();
+                let keyed = data
+                    .entry(game_state.zobrist_key())
+                    .or_insert(KeyedElement {
+                        verification_tag,
+                        element: TranspositionTableElement::default(),
+                    });
+                // a different game state with the same key, its bounds say nothing about this one
+                if keyed.verification_tag != verification_tag {
+                    keyed.verification_tag = verification_tag;
+                    keyed.element = TranspositionTableElement::default();
+                }
+                Some(&mut keyed.element)
+            }
+            TranspositionTableData::Fixed {
+                slots,
+                bucket_size,
+                policy,
+            } => {
+                let key = game_state.zobrist_key();
+                let verification_tag = game_state.verification_tag();
+                let bucket = Self::bucket_mut(slots, *bucket_size, key);
+                let slot = Self::choose_slot(
+                    bucket,
+                    *policy,
+                    key,
+                    verification_tag,
+                    depth,
+                    work,
+                    self.generation,
+                )?;
+                if !slot.holds(key, verification_tag) {
+                    slot.reset(key, verification_tag);
+                }
+                Some(&mut slot.element)
+            }
         }
     }
+}
+
+// the transposition table to start with when there isn't a saved one, see load_transposition_table
+pub enum TableSize {
+    // a key-only table which grows as needed, with room for this many game states to start with
+    Entries(usize),
+    // a fixed table which never uses more than this many megabytes, see TranspositionTable::with_size_mb
+    Megabytes(usize, ReplacementPolicy),
+}
+
+// the table saved by an earlier run if there is one, otherwise an empty table
+pub fn load_transposition_table<T: GameState<T>>(
+    filepath: &str,
+    max_table_depth: u32,
+    size: TableSize,
+) -> TranspositionTable<T> {
+    match TranspositionTable::read_from_file(filepath) {
+        Ok(transposition_table) => {
+            info!(
+                "Loaded {} transposition table entries from {}",
+                transposition_table.len(),
+                filepath
+            );
+            transposition_table
+        }
+        Err(error) => {
+            if error.kind() != io::ErrorKind::NotFound {
+                warn!("Couldn't load {}: {}", filepath, error);
+            }
+            match size {
+                TableSize::Entries(capacity) => {
+                    TranspositionTable::with_capacity_keys_only(capacity, max_table_depth)
+                }
+                TableSize::Megabytes(megabytes, policy) => {
+                    TranspositionTable::with_size_mb(megabytes, max_table_depth, policy)
+                }
+            }
+        }
+    }
+}
+
+// i32::MIN and i32::MAX stand for no bound, so they swap rather than overflow
+fn negate(value: i32) -> i32 {
+    match value {
+        i32::MIN => i32::MAX,
+        i32::MAX => i32::MIN,
+        value => -value,
+    }
+}
+
+fn write_entry(
+    writer: &mut impl Write,
+    key: u64,
+    verification_tag: u32,
+    element: &TranspositionTableElement,
+) -> io::Result<()> {
+    writer.write_all(&key.to_le_bytes())?;
+    writer.write_all(&verification_tag.to_le_bytes())?;
+    writer.write_all(&element.lower_bound.bound.to_le_bytes())?;
+    writer.write_all(&element.lower_bound.depth.to_le_bytes())?;
+    writer.write_all(&element.upper_bound.bound.to_le_bytes())?;
+    writer.write_all(&element.upper_bound.depth.to_le_bytes())?;
+    let best_child = element.best_child.map_or(NO_BEST_CHILD, u32::from);
+    writer.write_all(&best_child.to_le_bytes())?;
+    writer.write_all(&element.work.to_le_bytes())
+}
+
+fn read_entry(reader: &mut impl Read) -> io::Result<(u64, u32, TranspositionTableElement)> {
+    let key = read_u64(reader)?;
+    let verification_tag = read_u32(reader)?;
+    let lower_bound = Bound {
+        bound: read_u32(reader)? as i32,
+        depth: read_u32(reader)?,
+    };
+    let upper_bound = Bound {
+        bound: read_u32(reader)? as i32,
+        depth: read_u32(reader)?,
+    };
+    let best_child = match read_u32(reader)? {
+        NO_BEST_CHILD => None,
+        best_child => Some(
+            u16::try_from(best_child)
+                .map_err(|_| invalid_data(format!("best child {} out of range", best_child)))?,
+        ),
+    };
+    let work = read_u32(reader)?;
+    let element = TranspositionTableElement {
+        lower_bound,
+        upper_bound,
+        best_child,
+        generation: 0,
+        work,
+    };
+    Ok((key, verification_tag, element))
+}
+
+// refuse a count of items which would need more bytes than are left in the file
+fn check_fits(
+    reader: &mut impl Seek,
+    file_len: u64,
+    count: u64,
+    item_bytes: u64,
+    what: &str,
+) -> io::Result<()> {
+    let remaining = file_len.saturating_sub(reader.stream_position()?);
+    if count
+        .checked_mul(item_bytes)
+        .is_none_or(|bytes| bytes > remaining)
+    {
+        return Err(invalid_data(format!(
+            "{} {} don't fit in the {} bytes left in the file",
+            count, what, remaining
+        )));
+    }
+    Ok(())
+}
+
+fn read_u8(reader: &mut impl Read) -> io::Result<u8> {
+    let mut bytes = [0; 1];
+    reader.read_exact(&mut bytes)?;
+    Ok(bytes[0])
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_u64(reader: &mut impl Read) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn invalid_data(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn policy_to_byte(policy: ReplacementPolicy) -> u8 {
+    match policy {
+        ReplacementPolicy::AlwaysReplace => 0,
+        ReplacementPolicy::DepthPreferred => 1,
+        ReplacementPolicy::TwoTier => 2,
+    }
+}
 
-    pub fn lookup(&self, game_state: &T) -> Option<&TranspositionTableElement> {
-        self.data.get(game_state)
+fn policy_from_byte(byte: u8) -> io::Result<ReplacementPolicy> {
+    match byte {
+        0 => Ok(ReplacementPolicy::AlwaysReplace),
+        1 => Ok(ReplacementPolicy::DepthPreferred),
+        2 => Ok(ReplacementPolicy::TwoTier),
+        _ => Err(invalid_data(format!("unknown replacement policy {}", byte))),
     }
+}
 
-    pub fn get(&mut self, game_state: &T) -> &mut TranspositionTableElement {
-        // TODO avoid cloning here if possible
-        self.data
-            .entry(game_state.clone())
-            .or_insert(TranspositionTableElement::default())
+// zobrist keys are already random so hashing them again is wasted work
+#[derive(Default)]
+struct KeyHasher {
+    hash: u64,
+}
+
+impl Hasher for KeyHasher {
+    fn finish(&self) -> u64 {
+        self.hash
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        // only u64 keys are expected, but fold in anything else rather than ignore it
+        for &byte in bytes {
+            self.hash = self.hash.rotate_left(8) ^ byte as u64;
+        }
+    }
+
+    fn write_u64(&mut self, key: u64) {
+        self.hash = key;
     }
 }
 
@@ -38,6 +862,12 @@ impl<T: GameState<T>> TranspositionTable<T> {
 pub struct TranspositionTableElement {
     pub lower_bound: Bound,
     pub upper_bound: Bound,
+    // index in get_children's order of the child which gave the value last time, searched first on the next visit
+    pub best_child: Option<u16>,
+    // generation of the table when this was last stored or looked up
+    generation: u16,
+    // nodes visited by the search which last stored this, so roughly how much work it would take to redo
+    work: u32,
 }
 
 #[derive(Clone, Debug)]
@@ -46,7 +876,74 @@ pub struct Bound {
     pub depth: u32,
 }
 
+impl Bound {
+    // take the new bound unless this one came from a deeper search
+    fn store(&mut self, bound: i32, depth: u32) {
+        if depth >= self.depth {
+            self.bound = bound;
+            self.depth = depth;
+        }
+    }
+}
+
 impl TranspositionTableElement {
+    // record the result of an alpha-beta search with the given window, which visited work nodes
+    // best_child is None when the search didn't find one, then the previous best child is kept
+    // a bound is only replaced by one from a search at least as deep, a shallower search knows less about the
+    // game state, e.g. a depth limited search mustn't overwrite an exact bound an earlier search left behind
+    pub fn store(
+        &mut self,
+        value: i32,
+        alpha: i32,
+        beta: i32,
+        depth: u32,
+        best_child: Option<usize>,
+        work: u32,
+    ) {
+        if depth >= self.depth() {
+            self.work = work;
+            if let Some(best_child) = best_child {
+                self.best_child = u16::try_from(best_child).ok();
+            }
+        }
+
+        // fail low: we have a new upper bound
+        if value <= alpha {
+            self.upper_bound.store(value, depth);
+        }
+        // accurate value for alpha (won't happen in a zero window)
+        if value > alpha && value < beta {
+            self.lower_bound.store(value, depth);
+            self.upper_bound.store(value, depth);
+        }
+        // fail high: we have a new lower bound
+        if value >= beta {
+            self.lower_bound.store(value, depth);
+        }
+    }
+
+    // the bounds for the mirror image of the game state this is stored for, see GameState::canonical_form
+    fn negated(&self) -> Self {
+        TranspositionTableElement {
+            lower_bound: Bound {
+                bound: negate(self.upper_bound.bound),
+                depth: self.upper_bound.depth,
+            },
+            upper_bound: Bound {
+                bound: negate(self.lower_bound.bound),
+                depth: self.lower_bound.depth,
+            },
+            best_child: self.best_child,
+            generation: self.generation,
+            work: self.work,
+        }
+    }
+
+    // how deep the search behind this entry went, so how much work it would take to redo
+    fn depth(&self) -> u32 {
+        max(self.lower_bound.depth, self.upper_bound.depth)
+    }
+
     fn default() -> Self {
         TranspositionTableElement {
             lower_bound: Bound {
@@ -57,6 +954,233 @@ impl TranspositionTableElement {
                 bound: i32::MAX,
                 depth: 0,
             },
+            best_child: None,
+            generation: 0,
+            work: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::negate;
+    use crate::mancala::MancalaGameState;
+    use crate::minimax::{GameState, ReplacementPolicy, TranspositionTable};
+
+    // a few different game states, half with player 2 to move so they're stored under their canonical form
+    fn game_states() -> Vec<MancalaGameState> {
+        let mut game_states = vec![MancalaGameState::default()];
+        let mut children_cache = Vec::new();
+        while game_states.len() < 50 {
+            let game_state = game_states[game_states.len() / 2];
+            for child in game_state.get_children(&mut children_cache) {
+                if !game_states.contains(child) {
+                    game_states.push(*child);
+                }
+            }
+        }
+        game_states
+    }
+
+    fn tables() -> Vec<TranspositionTable<MancalaGameState>> {
+        vec![
+            TranspositionTable::new(u32::MAX),
+            TranspositionTable::with_capacity_keys_only(100, u32::MAX),
+            TranspositionTable::with_size_bytes(1 << 16, u32::MAX, ReplacementPolicy::TwoTier),
+        ]
+    }
+
+    // every entry of a search without a depth limit has the same depth, so the work decides what a full table keeps
+    #[test]
+    fn depth_preferred_keeps_the_most_work() {
+        let game_states = game_states();
+        let (first, second, third) = (&game_states[0], &game_states[2], &game_states[4]);
+        // a single slot
+        let mut table =
+            TranspositionTable::with_size_bytes(0, u32::MAX, ReplacementPolicy::DepthPreferred);
+        table.store(first, 1, i32::MIN, i32::MAX, u32::MAX, None, 100);
+        table.store(second, 2, i32::MIN, i32::MAX, u32::MAX, None, 10);
+        assert!(table.lookup(first).is_some());
+        assert!(table.lookup(second).is_none());
+
+        table.store(third, 3, i32::MIN, i32::MAX, u32::MAX, None, 1000);
+        assert!(table.lookup(first).is_none());
+        assert!(table.lookup(third).is_some());
+
+        // the always-replace slot takes what the depth-preferred one refuses
+        let mut table =
+            TranspositionTable::with_size_bytes(0, u32::MAX, ReplacementPolicy::TwoTier);
+        table.store(first, 1, i32::MIN, i32::MAX, u32::MAX, None, 100);
+        table.store(second, 2, i32::MIN, i32::MAX, u32::MAX, None, 10);
+        table.store(third, 3, i32::MIN, i32::MAX, u32::MAX, None, 20);
+        assert!(table.lookup(first).is_some());
+        assert!(table.lookup(second).is_none());
+        assert!(table.lookup(third).is_some());
+    }
+
+    fn temporary_filepath(name: &str) -> String {
+        std::env::temp_dir()
+            .join(format!("{}-{}.bin", name, std::process::id()))
+            .to_str()
+            .unwrap()
+            .to_string()
+    }
+
+    fn read_error(filepath: &str) -> std::io::ErrorKind {
+        TranspositionTable::<MancalaGameState>::read_from_file(filepath)
+            .err()
+            .expect("the file should have been refused")
+            .kind()
+    }
+
+    #[test]
+    fn file_round_trip() {
+        let game_states = game_states();
+        let filepath = temporary_filepath("transposition-table-round-trip");
+        for mut table in tables() {
+            for (value, game_state) in game_states.iter().enumerate() {
+                table.store(game_state, value as i32, i32::MIN, i32::MAX, 7, Some(1), 3);
+            }
+            table.write_to_file(&filepath).unwrap();
+            let mut loaded =
+                TranspositionTable::<MancalaGameState>::read_from_file(&filepath).unwrap();
+
+            assert_eq!(loaded.len(), table.len());
+            assert_eq!(loaded.capacity(), table.capacity());
+            for game_state in game_states.iter() {
+                let element = table.lookup(game_state).unwrap();
+                let loaded_element = loaded.lookup(game_state).unwrap();
+                assert_eq!(loaded_element.lower_bound.bound, element.lower_bound.bound);
+                assert_eq!(loaded_element.upper_bound.bound, element.upper_bound.bound);
+                assert_eq!(loaded_element.best_child, element.best_child);
+            }
         }
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn file_rejects_bad_headers() {
+        let mut table = TranspositionTable::<MancalaGameState>::with_capacity_keys_only(100, 20);
+        for (value, game_state) in game_states().iter().enumerate() {
+            table.store(
+                game_state,
+                value as i32,
+                i32::MIN,
+                i32::MAX,
+                u32::MAX,
+                None,
+                1,
+            );
+        }
+        let filepath = temporary_filepath("transposition-table-bad-headers");
+        table.write_to_file(&filepath).unwrap();
+        let bytes = std::fs::read(&filepath).unwrap();
+        let game_name_len = u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize;
+        let num_entries_at = 12 + game_name_len + 4 + 1;
+
+        let corrupt = |at: usize, replacement: &[u8]| {
+            let mut bytes = bytes.clone();
+            bytes[at..at + replacement.len()].copy_from_slice(replacement);
+            std::fs::write(&filepath, bytes).unwrap();
+        };
+
+        corrupt(0, b"XXXX");
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+        corrupt(4, &99u32.to_le_bytes());
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+        // another game's name, then lengths far too big for the file
+        corrupt(12, b"X");
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+        corrupt(8, &u32::MAX.to_le_bytes());
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+        corrupt(num_entries_at, &u64::MAX.to_le_bytes());
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+        corrupt(num_entries_at, &(1u64 << 40).to_le_bytes());
+        assert_eq!(read_error(&filepath), std::io::ErrorKind::InvalidData);
+
+        std::fs::write(&filepath, &bytes[..bytes.len() - 1]).unwrap();
+        assert!(TranspositionTable::<MancalaGameState>::read_from_file(&filepath).is_err());
+        std::fs::remove_file(&filepath).unwrap();
+    }
+
+    #[test]
+    fn lookup_keeps_entries_fresh() {
+        let game_states = game_states();
+        for mut table in tables() {
+            for (value, game_state) in game_states.iter().enumerate() {
+                table.store(
+                    game_state,
+                    value as i32,
+                    i32::MIN,
+                    i32::MAX,
+                    u32::MAX,
+                    None,
+                    1,
+                );
+            }
+            for _ in 0..3 {
+                table.new_generation();
+                table.lookup(&game_states[0]);
+            }
+
+            assert_eq!(table.remove_stale(1), game_states.len() - 1);
+            assert_eq!(table.len(), 1);
+            assert!(table.lookup(&game_states[0]).is_some());
+        }
+    }
+
+    // a game state and its mirror image share an entry, the bounds are negated as the other player is maximising
+    #[test]
+    fn mirrored_game_states_share_an_entry() {
+        for game_state in game_states() {
+            let mirrored = game_state.swap_players();
+            for mut table in tables() {
+                table.store(&game_state, 7, 0, 5, u32::MAX, None, 1);
+                let element = table.lookup(&game_state).unwrap();
+                let mirrored_element = table.lookup(&mirrored).unwrap();
+                assert_eq!(element.lower_bound.bound, 7);
+                assert_eq!(
+                    mirrored_element.lower_bound.bound,
+                    negate(element.upper_bound.bound)
+                );
+                assert_eq!(
+                    mirrored_element.upper_bound.bound,
+                    negate(element.lower_bound.bound)
+                );
+                assert_eq!(table.len(), 1);
+            }
+
+            let mut table = TranspositionTable::new(u32::MAX);
+            table.use_canonical_forms = false;
+            table.store(&game_state, 7, 0, 5, u32::MAX, None, 1);
+            assert!(table.lookup(&mirrored).is_none());
+        }
+    }
+
+    // a depth limited search of a game state an earlier exact search solved mustn't lose the exact bounds
+    #[test]
+    fn shallower_results_keep_deeper_bounds() {
+        let game_states = game_states();
+        for mut table in tables() {
+            for game_state in game_states.iter() {
+                table.store(game_state, 3, i32::MIN, i32::MAX, u32::MAX, Some(1), 100);
+                table.store(game_state, 9, 0, 5, 4, Some(2), 1);
+                table.store(game_state, -9, 0, 5, 4, Some(2), 1);
+                let element = table.lookup(game_state).unwrap();
+                assert_eq!(element.lower_bound.bound, 3);
+                assert_eq!(element.lower_bound.depth, u32::MAX);
+                assert_eq!(element.upper_bound.bound, 3);
+                assert_eq!(element.upper_bound.depth, u32::MAX);
+                assert_eq!(element.best_child, Some(1));
+            }
+        }
+
+        // deeper results still replace shallower ones
+        let mut table = TranspositionTable::new(u32::MAX);
+        let game_state = &game_states[0];
+        table.store(game_state, 9, 0, 5, 4, None, 1);
+        table.store(game_state, 7, 0, 5, 6, None, 1);
+        table.store(game_state, 8, 0, 5, 2, None, 1);
+        assert_eq!(table.lookup(game_state).unwrap().lower_bound.bound, 7);
     }
 }
@@ -0,0 +1,19 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+
+// write a file next to filepath then rename it over filepath, so a crash part way through leaves the old file whole
+// and processes which have the old file mapped keep it
+pub(crate) fn write_file_replacing<T>(
+    filepath: &str,
+    write: impl FnOnce(&mut BufWriter<File>) -> io::Result<T>,
+) -> io::Result<T> {
+    let temporary_filepath = format!("{}.tmp", filepath);
+    let mut writer = BufWriter::new(File::create(&temporary_filepath)?);
+    let result = write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|error| error.into_error())?
+        .sync_all()?;
+    fs::rename(&temporary_filepath, filepath)?;
+    Ok(result)
+}